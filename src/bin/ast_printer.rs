@@ -1,18 +1,19 @@
 use lox::expr::{Expr, Literal};
 use lox::tools::ast_printer;
+use lox::token::Span;
 use lox::{Token, TokenType as TT};
 
 fn main() {
   let e = Box::new(Expr::Binary(
     Box::new(Expr::Unary(
-      Token::new(TT::Minus, 1),
+      Token::new(TT::Minus, Span::at_line(1)),
       Box::new(Expr::Literal(Literal::Number(123.0))),
     )),
-    Token::new(TT::Star, 1),
+    Token::new(TT::Star, Span::at_line(1)),
     Box::new(Expr::Grouping(Box::new(Expr::Literal(Literal::Number(
       45.67,
     ))))),
   ));
 
-  ast_printer::print_ast(e);
+  ast_printer::print_ast(&e);
 }
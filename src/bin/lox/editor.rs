@@ -0,0 +1,422 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, prelude::*, IsTerminal};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+const HISTORY_FILE: &str = ".lox_history";
+const HISTORY_MAX: usize = 1000;
+
+// A small line editor for the REPL: cursor movement, history (kept in
+// ~/.lox_history between sessions), and tab completion. When stdin isn't a
+// terminal, it just reads plain lines, so piping code in still works.
+//
+// There's no termios in std, so raw mode comes from shelling out to stty.
+pub struct Editor {
+  interactive: bool,
+  history: Vec<String>,
+  history_path: Option<PathBuf>,
+  lines_saved: usize, // how long the history file is, as far as we know
+}
+
+// What the line being edited looks like right now.
+struct Line {
+  chars: Vec<char>,
+  cursor: usize,
+}
+
+enum Key {
+  Char(char),
+  Enter,
+  Tab,
+  Backspace,
+  Delete,
+  Left,
+  Right,
+  Up,
+  Down,
+  Home,
+  End,
+  KillToStart,
+  KillToEnd,
+  Interrupt,
+  Eof,
+  Other,
+}
+
+// Puts the terminal into raw mode for as long as it's alive.
+struct RawMode {
+  saved: String,
+}
+
+impl Editor {
+  pub fn new() -> Self {
+    let interactive = io::stdin().is_terminal() && io::stdout().is_terminal();
+    let history_path =
+      std::env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY_FILE));
+
+    let saved: Vec<String> = history_path
+      .as_ref()
+      .and_then(|path| fs::read_to_string(path).ok())
+      .map(|contents| contents.lines().map(String::from).collect())
+      .unwrap_or_default();
+
+    // only the most recent lines are worth keeping
+    let history = saved[saved.len().saturating_sub(HISTORY_MAX)..].to_vec();
+
+    Editor {
+      interactive,
+      history,
+      history_path,
+      lines_saved: saved.len(),
+    }
+  }
+
+  // Returns the next line (with its newline), or None at end of input.
+  // Completions are offered from the given names, as well as the keywords.
+  pub fn read_line(
+    &mut self,
+    prompt: &str,
+    names: &[String],
+  ) -> io::Result<Option<String>> {
+    print!("{prompt}");
+    io::stdout().flush()?;
+
+    if !self.interactive {
+      return read_plain_line();
+    }
+
+    let raw = match RawMode::enter() {
+      Some(raw) => raw,
+      None => return read_plain_line(),
+    };
+
+    let line = self.edit(prompt, names);
+    drop(raw);
+    println!();
+
+    let line = line?;
+    if let Some(line) = &line {
+      self.remember(line);
+    }
+
+    Ok(line.map(|line| line + "\n"))
+  }
+
+  fn edit(&mut self, prompt: &str, names: &[String]) -> io::Result<Option<String>> {
+    let mut line = Line {
+      chars: vec![],
+      cursor: 0,
+    };
+
+    // where we are in the history; history.len() is the line being typed,
+    // which we stash while looking at older ones
+    let mut position = self.history.len();
+    let mut draft = String::new();
+
+    loop {
+      match read_key()? {
+        Key::Char(c) => line.insert(c),
+        Key::Enter => return Ok(Some(line.text())),
+        Key::Tab => complete(&mut line, prompt, names),
+        Key::Backspace if line.cursor > 0 => {
+          line.cursor -= 1;
+          line.chars.remove(line.cursor);
+        },
+        Key::Delete if line.cursor < line.chars.len() => {
+          line.chars.remove(line.cursor);
+        },
+        Key::Left => line.cursor = line.cursor.saturating_sub(1),
+        Key::Right => line.cursor = (line.cursor + 1).min(line.chars.len()),
+        Key::Home => line.cursor = 0,
+        Key::End => line.cursor = line.chars.len(),
+        Key::Up if position > 0 => {
+          if position == self.history.len() {
+            draft = line.text();
+          }
+
+          position -= 1;
+          line.replace(&self.history[position]);
+        },
+        Key::Down if position < self.history.len() => {
+          position += 1;
+
+          match self.history.get(position) {
+            Some(entry) => line.replace(entry),
+            None => line.replace(&draft),
+          }
+        },
+        Key::KillToStart => {
+          line.chars.drain(..line.cursor);
+          line.cursor = 0;
+        },
+        Key::KillToEnd => line.chars.truncate(line.cursor),
+        Key::Interrupt => line.replace(""),
+        Key::Eof if line.chars.is_empty() => return Ok(None),
+        _ => (),
+      }
+
+      line.redraw(prompt)?;
+    }
+  }
+
+  fn remember(&mut self, line: &str) {
+    if line.trim().is_empty() || self.history.last().is_some_and(|last| last == line) {
+      return;
+    }
+
+    self.history.push(line.to_string());
+
+    if self.history.len() > HISTORY_MAX {
+      self.history.remove(0);
+    }
+
+    // History is a nicety, so if we can't save it, we just don't. Mostly we
+    // add to the end of the file, but once that would take it past the cap,
+    // we write out just what we're keeping instead.
+    let Some(path) = &self.history_path else {
+      return;
+    };
+
+    if self.lines_saved >= HISTORY_MAX {
+      let contents = self.history.join("\n") + "\n";
+      if fs::write(path, contents).is_ok() {
+        self.lines_saved = self.history.len();
+      }
+    } else {
+      let file = OpenOptions::new().create(true).append(true).open(path);
+      if let Ok(mut file) = file {
+        if writeln!(file, "{line}").is_ok() {
+          self.lines_saved += 1;
+        }
+      }
+    }
+  }
+}
+
+impl Line {
+  fn text(&self) -> String {
+    self.chars.iter().collect()
+  }
+
+  fn insert(&mut self, c: char) {
+    self.chars.insert(self.cursor, c);
+    self.cursor += 1;
+  }
+
+  fn replace(&mut self, text: &str) {
+    self.chars = text.chars().collect();
+    self.cursor = self.chars.len();
+  }
+
+  fn redraw(&self, prompt: &str) -> io::Result<()> {
+    let mut out = io::stdout();
+
+    // back to the start, draw everything, clear whatever's left over, and
+    // then put the cursor where it belongs
+    write!(out, "\r{prompt}{}\x1b[K", self.text())?;
+
+    let behind = self.chars.len() - self.cursor;
+    if behind > 0 {
+      write!(out, "\x1b[{behind}D")?;
+    }
+
+    out.flush()
+  }
+
+  // the identifier-ish bit just before the cursor
+  fn word_start(&self) -> usize {
+    let mut start = self.cursor;
+
+    while start > 0 && is_word_char(self.chars[start - 1]) {
+      start -= 1;
+    }
+
+    start
+  }
+}
+
+// Completes the word before the cursor as far as all the candidates agree;
+// if that doesn't get us anywhere, lists them.
+fn complete(line: &mut Line, prompt: &str, names: &[String]) {
+  let start = line.word_start();
+  let prefix: String = line.chars[start..line.cursor].iter().collect();
+
+  if prefix.is_empty() {
+    return;
+  }
+
+  let mut matches: Vec<&str> = lox::token::KEYWORDS
+    .iter()
+    .map(|(word, _)| *word)
+    .chain(names.iter().map(String::as_str))
+    .filter(|candidate| candidate.starts_with(&prefix))
+    .collect();
+
+  matches.sort();
+  matches.dedup();
+
+  let Some(first) = matches.first() else {
+    return;
+  };
+
+  let common = matches.iter().fold(*first, |common, candidate| {
+    let len = common
+      .chars()
+      .zip(candidate.chars())
+      .take_while(|(a, b)| a == b)
+      .map(|(a, _)| a.len_utf8())
+      .sum();
+
+    &common[..len]
+  });
+
+  if common.len() > prefix.len() {
+    for c in common[prefix.len()..].chars() {
+      line.insert(c);
+    }
+
+    if matches.len() == 1 {
+      line.insert(' ');
+    }
+  } else if matches.len() > 1 {
+    print!("\r\n{}\r\n{prompt}", matches.join("  "));
+  }
+}
+
+fn is_word_char(c: char) -> bool {
+  c.is_alphanumeric() || c == '_'
+}
+
+fn read_plain_line() -> io::Result<Option<String>> {
+  let mut line = String::new();
+
+  match io::stdin().read_line(&mut line)? {
+    0 => Ok(None),
+    _ => Ok(Some(line)),
+  }
+}
+
+fn read_byte() -> io::Result<Option<u8>> {
+  let mut buf = [0];
+
+  match io::stdin().read(&mut buf)? {
+    0 => Ok(None),
+    _ => Ok(Some(buf[0])),
+  }
+}
+
+fn read_key() -> io::Result<Key> {
+  let Some(byte) = read_byte()? else {
+    return Ok(Key::Eof);
+  };
+
+  let key = match byte {
+    b'\r' | b'\n' => Key::Enter,
+    b'\t' => Key::Tab,
+    0x7f | 0x08 => Key::Backspace,
+    0x01 => Key::Home,        // ^A
+    0x05 => Key::End,         // ^E
+    0x02 => Key::Left,        // ^B
+    0x06 => Key::Right,       // ^F
+    0x10 => Key::Up,          // ^P
+    0x0e => Key::Down,        // ^N
+    0x15 => Key::KillToStart, // ^U
+    0x0b => Key::KillToEnd,   // ^K
+    0x03 => Key::Interrupt,   // ^C
+    0x04 => Key::Eof,         // ^D
+    0x1b => read_escape()?,
+    byte if byte < 0x20 => Key::Other,
+    byte => read_char(byte)?,
+  };
+
+  Ok(key)
+}
+
+// The rest of an escape sequence, as sent by the arrow keys and friends.
+fn read_escape() -> io::Result<Key> {
+  let intro = read_byte()?;
+  if intro != Some(b'[') && intro != Some(b'O') {
+    return Ok(Key::Other);
+  }
+
+  // parameters, then a final byte somewhere in @..~
+  let mut params = vec![];
+  let last = loop {
+    match read_byte()? {
+      Some(byte @ 0x40..=0x7e) => break byte,
+      Some(byte) => params.push(byte),
+      None => return Ok(Key::Other),
+    }
+  };
+
+  let key = match (last, params.as_slice()) {
+    (b'A', _) => Key::Up,
+    (b'B', _) => Key::Down,
+    (b'C', _) => Key::Right,
+    (b'D', _) => Key::Left,
+    (b'H', _) | (b'~', b"1" | b"7") => Key::Home,
+    (b'F', _) | (b'~', b"4" | b"8") => Key::End,
+    (b'~', b"3") => Key::Delete,
+    _ => Key::Other,
+  };
+
+  Ok(key)
+}
+
+// Reads the rest of a UTF-8 character, given its first byte.
+fn read_char(first: u8) -> io::Result<Key> {
+  let len = match first {
+    0xf0.. => 4,
+    0xe0.. => 3,
+    0xc0.. => 2,
+    _ => 1,
+  };
+
+  let mut bytes = vec![first];
+  for _ in 1..len {
+    match read_byte()? {
+      Some(byte) => bytes.push(byte),
+      None => break,
+    }
+  }
+
+  let key = match std::str::from_utf8(&bytes) {
+    Ok(s) => s.chars().next().map_or(Key::Other, Key::Char),
+    Err(_) => Key::Other,
+  };
+
+  Ok(key)
+}
+
+impl RawMode {
+  fn enter() -> Option<Self> {
+    let saved = stty(&["-g"])?;
+    stty(&["-icanon", "-echo", "-isig", "min", "1"])?;
+
+    Some(RawMode {
+      saved: saved.trim().to_string(),
+    })
+  }
+}
+
+impl Drop for RawMode {
+  fn drop(&mut self) {
+    stty(&[&self.saved]);
+  }
+}
+
+// Runs stty against our terminal, returning what it printed if it worked.
+fn stty(args: &[&str]) -> Option<String> {
+  let output = Command::new("stty")
+    .args(args)
+    .stdin(Stdio::inherit())
+    .stderr(Stdio::null())
+    .output()
+    .ok()?;
+
+  if !output.status.success() {
+    return None;
+  }
+
+  String::from_utf8(output.stdout).ok()
+}
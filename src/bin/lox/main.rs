@@ -0,0 +1,398 @@
+mod editor;
+
+use std::fs;
+use std::io::IsTerminal;
+use std::path::Path;
+use std::process;
+use std::rc::Rc;
+
+use editor::Editor;
+use lox::chunk::Function;
+use lox::diagnostics;
+use lox::disassembler::disassemble_function;
+use lox::gc::GcConfig;
+use lox::serialize;
+use lox::stmt::Stmt;
+use lox::tools::ast_printer;
+use lox::{Compiler, Error, Interpreter, Parser, Resolver, Result, Scanner, Token, Vm};
+
+// which engine actually runs the code
+#[derive(Debug, Clone, Copy)]
+enum Backend {
+  TreeWalk(GcConfig),
+  Vm(GcConfig),
+}
+
+// Whatever is actually running the code. The REPL keeps one of these around
+// for the whole session, so that definitions stick from one line to the next.
+enum Session {
+  TreeWalk(Interpreter),
+  Vm(Box<Vm>),
+}
+
+// Where some code came from, so that errors in it can be shown in context.
+#[derive(Clone, Copy)]
+struct Origin<'a> {
+  name: &'a str,
+  source: &'a str,
+}
+
+// The tree-walker recurses on the Rust stack for every Lox call, a good few
+// frames' worth each, so we give it more room than the main thread gets.
+const STACK_SIZE: usize = 64 * 1024 * 1024;
+
+fn main() -> Result<()> {
+  // errors can't leave the thread, so they get reported the way they would
+  // have been coming out of main
+  let run = || {
+    if let Err(err) = run() {
+      eprintln!("Error: {err:?}");
+      process::exit(1);
+    }
+  };
+
+  let thread = std::thread::Builder::new().stack_size(STACK_SIZE).spawn(run)?;
+  thread.join().unwrap_or_else(|panic| std::panic::resume_unwind(panic));
+  Ok(())
+}
+
+fn run() -> Result<()> {
+  let mut use_vm = false;
+  let mut gc = GcConfig::default();
+  let mut disassemble = false;
+  let mut args = vec![];
+
+  for arg in std::env::args().skip(1) {
+    match arg.as_str() {
+      "--backend=tree" => use_vm = false,
+      "--backend=vm" => use_vm = true,
+      "--disassemble" => disassemble = true,
+      "--gc-stress" => gc.stress = true,
+      "--gc-log" => gc.log = true,
+      _ if arg.starts_with("--gc-threshold=") => {
+        match arg["--gc-threshold=".len()..].parse() {
+          Ok(bytes) => gc.initial_threshold = bytes,
+          Err(_) => usage(),
+        }
+      },
+      _ if arg.starts_with("--") => usage(),
+      _ => args.push(arg),
+    }
+  }
+
+  let backend = if use_vm { Backend::Vm(gc) } else { Backend::TreeWalk(gc) };
+
+  match args.as_slice() {
+    [cmd, input, flag, output] if cmd == "compile" && flag == "-o" => {
+      compile_file(input, output)?
+    },
+    [cmd, input] if cmd == "compile" => {
+      let output = Path::new(input).with_extension("loxc");
+      compile_file(input, &output.to_string_lossy())?
+    },
+    [cmd, path] if cmd == "run" => run_file(path, backend, gc)?,
+    [path] if disassemble => disassemble_file(path)?,
+    [path] => run_file(path, backend, gc)?,
+    [] if !disassemble => run_prompt(backend)?,
+    _ => usage(),
+  }
+
+  Ok(())
+}
+
+fn usage() -> ! {
+  println!("Usage: lox [--backend=tree|vm] [script]");
+  println!("       lox [--gc-stress] [--gc-log] [--gc-threshold=BYTES] ...");
+  println!("       lox compile script.lox [-o script.loxc]");
+  println!("       lox run script.loxc");
+  println!("       lox --disassemble script");
+  process::exit(64);
+}
+
+// Runs either a source file or a compiled one; compiled ones always run on
+// the VM, since that's all they're good for.
+fn run_file(path: &str, backend: Backend, gc: GcConfig) -> Result<()> {
+  let contents = fs::read(path)?;
+
+  if serialize::is_compiled(&contents) {
+    let result = serialize::read_program(&contents)
+      .and_then(|function| Vm::with_gc_config(gc).interpret(function));
+
+    exit_on_error(result, None);
+    return Ok(());
+  }
+
+  let source = exit_on_error(into_source(contents), None);
+  let origin = Origin::new(path, &source);
+  exit_on_error(Session::new(backend).run(origin), Some(origin));
+  Ok(())
+}
+
+fn compile_file(input: &str, output: &str) -> Result<()> {
+  let function = load_function(input)?;
+  fs::write(output, serialize::write_program(&function))?;
+  Ok(())
+}
+
+// compiles the script and dumps the bytecode for every function, rather than
+// running it
+fn disassemble_file(path: &str) -> Result<()> {
+  let function = load_function(path)?;
+  print!("{}", disassemble_function(&function));
+  Ok(())
+}
+
+// Gets compiled code for a file, whether it's source or already compiled. If
+// there's anything wrong with the code itself, this reports it and exits.
+fn load_function(path: &str) -> Result<Rc<Function>> {
+  let contents = fs::read(path)?;
+
+  if serialize::is_compiled(&contents) {
+    return Ok(exit_on_error(serialize::read_program(&contents), None));
+  }
+
+  let source = exit_on_error(into_source(contents), None);
+  let origin = Origin::new(path, &source);
+
+  let tokens = Scanner::new(source.clone()).into_tokens();
+  let result = parse(tokens).and_then(|stmts| compile(&stmts));
+
+  Ok(exit_on_error(result, Some(origin)))
+}
+
+fn into_source(contents: Vec<u8>) -> Result<String> {
+  String::from_utf8(contents).map_err(|_| Error::Io(std::io::ErrorKind::InvalidData.into()))
+}
+
+fn exit_on_error<T>(result: Result<T>, origin: Option<Origin>) -> T {
+  result.unwrap_or_else(|e| {
+    match origin {
+      Some(origin) => origin.report(&e),
+      None => eprintln!("{e}"),
+    }
+
+    process::exit(65);
+  })
+}
+
+impl<'a> Origin<'a> {
+  fn new(name: &'a str, source: &'a str) -> Self {
+    Origin { name, source }
+  }
+
+  fn report(&self, err: &Error) {
+    let color = std::io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none();
+    eprintln!("{}", diagnostics::render(err, self.name, self.source, color));
+  }
+}
+
+fn run_prompt(backend: Backend) -> Result<()> {
+  let mut editor = Editor::new();
+  let mut session = Session::new(backend);
+
+  // everything typed since the last complete chunk of code
+  let mut input = String::new();
+
+  loop {
+    let prompt = if input.is_empty() { "> " } else { "... " };
+    let names: Vec<_> = session.globals().into_iter().map(|(name, _)| name).collect();
+
+    let Some(line) = editor.read_line(prompt, &names)? else {
+      break;
+    };
+
+    if input.is_empty() && line.trim_start().starts_with(':') {
+      run_command(&mut session, backend, line.trim());
+      continue;
+    }
+
+    // a blank line means stop waiting, and report whatever's wrong
+    let give_up = !input.is_empty() && line.trim().is_empty();
+    input.push_str(&line);
+
+    if !give_up && is_incomplete(&input) {
+      continue;
+    }
+
+    let origin = Origin::new("<repl>", &input);
+
+    if let Err(err) = session.run_line(origin) {
+      origin.report(&err);
+    }
+
+    input.clear();
+  }
+
+  Ok(())
+}
+
+const REPL_HELP: &str = "\
+:help             show this message
+:load <file>      run a file in this session
+:env              list the globals and their values
+:ast <expr>       show how an expression parses
+:tokens <source>  show what the scanner makes of some source
+:reset            throw everything away and start over
+";
+
+// REPL commands all start with a colon, and get handled here rather than
+// being treated as code.
+fn run_command(session: &mut Session, backend: Backend, line: &str) {
+  let (cmd, arg) = match line.split_once(char::is_whitespace) {
+    Some((cmd, arg)) => (cmd, arg.trim()),
+    None => (line, ""),
+  };
+
+  match cmd {
+    ":help" => print!("{REPL_HELP}"),
+    ":load" if !arg.is_empty() => match fs::read_to_string(arg) {
+      Ok(source) => {
+        let origin = Origin::new(arg, &source);
+
+        if let Err(err) = session.run(origin) {
+          origin.report(&err);
+        }
+      },
+      Err(err) => eprintln!("couldn't read {arg}: {err}"),
+    },
+    ":env" => {
+      for (name, value) in session.globals() {
+        println!("{name} = {value}");
+      }
+    },
+    ":ast" if !arg.is_empty() => show_ast(arg),
+    ":tokens" if !arg.is_empty() => show_tokens(arg),
+    ":reset" => {
+      *session = Session::new(backend);
+      println!("(state cleared)");
+    },
+    _ => eprintln!("unknown command {line} (try :help)"),
+  }
+}
+
+fn show_ast(source: &str) {
+  let origin = Origin::new("<repl>", source);
+
+  let tokens = Scanner::new(source.to_string()).into_tokens();
+  let mut parser = Parser::new(tokens);
+
+  match parser.parse_expression() {
+    Ok(expr) => ast_printer::print_ast(&expr),
+    Err(_) => {
+      for err in parser.errors {
+        origin.report(&err);
+      }
+    },
+  }
+}
+
+fn show_tokens(source: &str) {
+  let mut scanner = Scanner::new(source.to_string());
+  let _ = scanner.scan_tokens();
+
+  for err in &scanner.errors {
+    Origin::new("<repl>", source).report(err);
+  }
+
+  for token in scanner.into_tokens() {
+    println!("{:4}:{:<3} {token}", token.line(), token.span.column);
+  }
+}
+
+// Whether the input looks like it stops partway through something: an open
+// string, or a block, call, or statement that the parser ran off the end of.
+fn is_incomplete(source: &str) -> bool {
+  let mut scanner = Scanner::new(source.to_string());
+
+  if scanner.scan_tokens().is_err() {
+    return scanner.ended_in_string();
+  }
+
+  let tokens = scanner.into_tokens();
+
+  if Parser::new(tokens.clone()).parse_expression().is_ok() {
+    return false;
+  }
+
+  let mut parser = Parser::new(tokens);
+  parser.parse().is_err() && parser.ran_out_of_input()
+}
+
+impl Session {
+  fn new(backend: Backend) -> Self {
+    match backend {
+      Backend::TreeWalk(gc) => {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_gc_config(gc);
+        interpreter.set_max_stack(STACK_SIZE / 2);
+        Session::TreeWalk(interpreter)
+      },
+      Backend::Vm(gc) => Session::Vm(Box::new(Vm::with_gc_config(gc))),
+    }
+  }
+
+  // names and printable values, sorted by name
+  fn globals(&self) -> Vec<(String, String)> {
+    let mut globals = match self {
+      Session::TreeWalk(interpreter) => interpreter
+        .globals()
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect(),
+      Session::Vm(vm) => vm.globals(),
+    };
+
+    globals.sort();
+    globals
+  }
+
+  // Runs some code, all the way through. Whatever stopped us, whether that's
+  // everything wrong with the code or the error it ran into, is left for the
+  // caller to report.
+  fn run(&mut self, origin: Origin) -> Result<()> {
+    let tokens = Scanner::new(origin.source.to_string()).into_tokens();
+    let stmts = parse(tokens)?;
+
+    match self {
+      Session::TreeWalk(interpreter) => {
+        resolve(&stmts)?;
+        interpreter.interpret(stmts)
+      },
+      Session::Vm(vm) => vm.interpret(compile(&stmts)?),
+    }
+  }
+
+  // Like run, but if the line is just an expression, we print its value.
+  fn run_line(&mut self, origin: Origin) -> Result<()> {
+    let tokens = Scanner::new(origin.source.to_string()).into_tokens();
+
+    let Ok(expr) = Parser::new(tokens).parse_expression() else {
+      return self.run(origin);
+    };
+
+    match self {
+      Session::TreeWalk(interpreter) => {
+        println!("{}", interpreter.eval(origin.source)?);
+        Ok(())
+      },
+      Session::Vm(vm) => vm.interpret(compile(&[Stmt::Print(expr)])?),
+    }
+  }
+}
+
+// These hand back everything that was wrong at once, as Error::Invalid, the
+// same way Interpreter::eval does, so it all gets rendered in one go.
+fn parse(tokens: Vec<Token>) -> Result<Vec<Stmt>> {
+  let mut parser = Parser::new(tokens);
+  parser.parse().map_err(|_| Error::Invalid(parser.errors))
+}
+
+fn resolve(stmts: &[Stmt]) -> Result<()> {
+  let mut resolver = Resolver::new();
+  resolver.resolve(stmts).map_err(|_| Error::Invalid(resolver.errors))
+}
+
+fn compile(stmts: &[Stmt]) -> Result<Rc<Function>> {
+  let mut compiler = Compiler::new();
+  compiler.compile(stmts).map_err(|_| Error::Invalid(compiler.errors))
+}
@@ -0,0 +1,200 @@
+use std::rc::Rc;
+
+// The opcodes for the bytecode VM. Operands follow the opcode inline in the
+// chunk: constant indices and jump offsets are two bytes (big-endian), and
+// everything else (local slots, upvalue indices, argument counts) is one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(u8)]
+pub enum OpCode {
+  Constant,
+  Nil,
+  True,
+  False,
+  Pop,
+  GetLocal,
+  SetLocal,
+  GetGlobal,
+  DefineGlobal,
+  SetGlobal,
+  GetUpvalue,
+  SetUpvalue,
+  GetProperty,
+  SetProperty,
+  GetSuper,
+  Equal,
+  Greater,
+  Less,
+  Add,
+  Subtract,
+  Multiply,
+  Divide,
+  Not,
+  Negate,
+  Print,
+  Jump,
+  JumpIfFalse,
+  Loop,
+  Call,
+  Closure,
+  CloseUpvalue,
+  Return,
+  Class,
+  Inherit,
+  Method,
+}
+
+impl OpCode {
+  // in discriminant order, so that we can go back from a byte
+  const ALL: [OpCode; 35] = [
+    OpCode::Constant,
+    OpCode::Nil,
+    OpCode::True,
+    OpCode::False,
+    OpCode::Pop,
+    OpCode::GetLocal,
+    OpCode::SetLocal,
+    OpCode::GetGlobal,
+    OpCode::DefineGlobal,
+    OpCode::SetGlobal,
+    OpCode::GetUpvalue,
+    OpCode::SetUpvalue,
+    OpCode::GetProperty,
+    OpCode::SetProperty,
+    OpCode::GetSuper,
+    OpCode::Equal,
+    OpCode::Greater,
+    OpCode::Less,
+    OpCode::Add,
+    OpCode::Subtract,
+    OpCode::Multiply,
+    OpCode::Divide,
+    OpCode::Not,
+    OpCode::Negate,
+    OpCode::Print,
+    OpCode::Jump,
+    OpCode::JumpIfFalse,
+    OpCode::Loop,
+    OpCode::Call,
+    OpCode::Closure,
+    OpCode::CloseUpvalue,
+    OpCode::Return,
+    OpCode::Class,
+    OpCode::Inherit,
+    OpCode::Method,
+  ];
+}
+
+impl TryFrom<u8> for OpCode {
+  type Error = u8;
+
+  fn try_from(byte: u8) -> std::result::Result<Self, u8> {
+    OpCode::ALL.get(byte as usize).copied().ok_or(byte)
+  }
+}
+
+// Constants are kept as plain data, rather than as VM values, so that a chunk
+// doesn't depend on any particular VM's heap. The VM turns these into real
+// values when it loads a function.
+#[derive(Debug, Clone)]
+pub enum Constant {
+  Number(f64),
+  String(String),
+  Function(Rc<Function>),
+}
+
+// A compiled function: the top-level script is one of these too, with an
+// empty name.
+#[derive(Debug, Default)]
+pub struct Function {
+  pub name: String,
+  pub arity: usize,
+  pub upvalue_count: usize,
+  pub chunk: Chunk,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Chunk {
+  pub code: Vec<u8>,
+  pub constants: Vec<Constant>,
+  // run-length encoded: (line, number of bytes in a row on that line)
+  lines: Vec<(usize, usize)>,
+}
+
+impl Chunk {
+  pub fn new() -> Self {
+    Chunk::default()
+  }
+
+  // for loading chunks back in; the caller is responsible for making sure the
+  // pieces actually go together
+  pub fn from_parts(
+    code: Vec<u8>,
+    constants: Vec<Constant>,
+    lines: Vec<(usize, usize)>,
+  ) -> Self {
+    Chunk {
+      code,
+      constants,
+      lines,
+    }
+  }
+
+  pub fn lines(&self) -> &[(usize, usize)] {
+    &self.lines
+  }
+
+  pub fn write(&mut self, byte: u8, line: usize) {
+    self.code.push(byte);
+
+    match self.lines.last_mut() {
+      Some((last, count)) if *last == line => *count += 1,
+      _ => self.lines.push((line, 1)),
+    }
+  }
+
+  pub fn write_op(&mut self, op: OpCode, line: usize) {
+    self.write(op as u8, line);
+  }
+
+  pub fn add_constant(&mut self, constant: Constant) -> usize {
+    self.constants.push(constant);
+    self.constants.len() - 1
+  }
+
+  pub fn line_at(&self, offset: usize) -> usize {
+    let mut seen = 0;
+
+    for &(line, count) in &self.lines {
+      seen += count;
+      if offset < seen {
+        return line;
+      }
+    }
+
+    self.lines.last().map_or(0, |&(line, _)| line)
+  }
+
+  pub fn read_u16(&self, offset: usize) -> u16 {
+    u16::from_be_bytes([self.code[offset], self.code[offset + 1]])
+  }
+}
+
+impl std::fmt::Display for Constant {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    match self {
+      Constant::Number(n) => write!(f, "{}", n),
+      Constant::String(s) => write!(f, "{}", s),
+      Constant::Function(func) => write!(f, "{}", func.display_name()),
+    }
+  }
+}
+
+impl Function {
+  pub fn display_name(&self) -> String {
+    if self.name.is_empty() {
+      "<script>".into()
+    } else {
+      format!("<function {}>", self.name)
+    }
+  }
+}
@@ -0,0 +1,738 @@
+use std::rc::Rc;
+
+use crate::chunk::{Chunk, Constant, Function, OpCode};
+use crate::expr::{Expr, Literal};
+use crate::stmt::Stmt;
+use crate::symbol::Symbol;
+use crate::token::Span;
+use crate::{Error, Result, Token, TokenType as TT};
+
+const MAX_LOCALS: usize = 256;
+const MAX_UPVALUES: usize = 256;
+const MAX_ARGS: usize = 255;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FunctionKind {
+  Script,
+  Function,
+  Initializer,
+  Method,
+}
+
+#[derive(Debug)]
+struct Local {
+  name: Symbol,
+  depth: Option<usize>, // None until the variable is fully defined
+  is_captured: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Upvalue {
+  index: u8,
+  is_local: bool,
+}
+
+// Everything we need to keep track of for the function currently being
+// compiled; these nest the same way function declarations do.
+#[derive(Debug)]
+struct FunctionState {
+  function: Function,
+  kind: FunctionKind,
+  locals: Vec<Local>,
+  upvalues: Vec<Upvalue>,
+  scope_depth: usize,
+}
+
+#[derive(Debug)]
+struct ClassState {
+  has_superclass: bool,
+}
+
+// The compiler turns the same AST the tree-walker runs into bytecode for the
+// VM. It does its own scope resolution as it goes (locals live in stack
+// slots, so it has to), which means it also reports the same static errors
+// the resolver does.
+#[derive(Debug)]
+pub struct Compiler {
+  states: Vec<FunctionState>,
+  classes: Vec<ClassState>,
+  current: Token, // the most recent token we've seen, for lines and errors
+  pub errors: Vec<Error>,
+}
+
+impl Default for Compiler {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl FunctionState {
+  fn new(name: String, kind: FunctionKind) -> Self {
+    // Slot zero is the function being called, or for methods, the receiver.
+    let slot_zero = match kind {
+      FunctionKind::Method | FunctionKind::Initializer => Symbol::THIS,
+      _ => Symbol::intern(""),
+    };
+
+    FunctionState {
+      function: Function {
+        name,
+        ..Function::default()
+      },
+      kind,
+      locals: vec![Local {
+        name: slot_zero,
+        depth: Some(0),
+        is_captured: false,
+      }],
+      upvalues: vec![],
+      scope_depth: 0,
+    }
+  }
+}
+
+impl Compiler {
+  pub fn new() -> Self {
+    Compiler {
+      states: vec![],
+      classes: vec![],
+      current: Token::new(TT::EOF, Span::at_line(1)),
+      errors: vec![],
+    }
+  }
+
+  pub fn has_errors(&self) -> bool {
+    !self.errors.is_empty()
+  }
+
+  pub fn compile(&mut self, statements: &[Stmt]) -> Result<Rc<Function>> {
+    self.states.push(FunctionState::new("".into(), FunctionKind::Script));
+
+    for stmt in statements {
+      self.statement(stmt);
+    }
+
+    self.emit_return();
+    let state = self.states.pop().expect("lost the script state");
+
+    if self.errors.is_empty() {
+      Ok(Rc::new(state.function))
+    } else {
+      Err(Error::CompileFailed)
+    }
+  }
+
+  fn statement(&mut self, stmt: &Stmt) {
+    match stmt {
+      Stmt::Empty => (),
+      Stmt::Block(block) => {
+        self.begin_scope();
+
+        for stmt in block {
+          self.statement(stmt);
+        }
+
+        self.end_scope();
+      },
+      Stmt::Expression(e) => {
+        self.expression(e);
+        self.emit_op(OpCode::Pop);
+      },
+      Stmt::Print(e) => {
+        self.expression(e);
+        self.emit_op(OpCode::Print);
+      },
+      Stmt::Var(name, init) => {
+        self.see(name);
+        let global = self.declare_variable(name);
+        self.expression(init);
+        self.define_variable(global);
+      },
+      Stmt::Function(name, params, body) => {
+        self.see(name);
+        let global = self.declare_variable(name);
+        self.mark_initialized(); // so it can refer to itself
+        self.function(name, params, body, FunctionKind::Function);
+        self.define_variable(global);
+      },
+      Stmt::Class(name, superclass, methods) => {
+        self.class(name, superclass.as_deref(), methods)
+      },
+      Stmt::If(cond, then_branch, else_branch) => {
+        self.expression(cond);
+
+        let then_jump = self.emit_jump(OpCode::JumpIfFalse);
+        self.emit_op(OpCode::Pop);
+        self.statement(then_branch);
+
+        let else_jump = self.emit_jump(OpCode::Jump);
+        self.patch_jump(then_jump);
+        self.emit_op(OpCode::Pop);
+        self.statement(else_branch);
+        self.patch_jump(else_jump);
+      },
+      Stmt::While(_, cond, body) => {
+        let loop_start = self.chunk().code.len();
+        self.expression(cond);
+
+        let exit_jump = self.emit_jump(OpCode::JumpIfFalse);
+        self.emit_op(OpCode::Pop);
+        self.statement(body);
+        self.emit_loop(loop_start);
+
+        self.patch_jump(exit_jump);
+        self.emit_op(OpCode::Pop);
+      },
+      Stmt::Return(keyword, value) => {
+        self.see(keyword);
+
+        if self.state().kind == FunctionKind::Script {
+          self.error(keyword, "Can't return from top-level code.");
+        }
+
+        match value {
+          Some(value) => {
+            if self.state().kind == FunctionKind::Initializer {
+              self.error(keyword, "Can't return a value from an initializer.");
+            }
+
+            self.expression(value);
+            self.emit_op(OpCode::Return);
+          },
+          None => self.emit_return(),
+        }
+      },
+    }
+  }
+
+  fn function(
+    &mut self,
+    name: &Token,
+    params: &[Token],
+    body: &[Stmt],
+    kind: FunctionKind,
+  ) {
+    self.states.push(FunctionState::new(name.lexeme(), kind));
+    self.begin_scope();
+
+    if params.len() > MAX_ARGS {
+      self.error(&params[MAX_ARGS], "Can't have more than 255 parameters.");
+    }
+
+    self.state_mut().function.arity = params.len();
+
+    for param in params {
+      self.declare_variable(param);
+      self.mark_initialized();
+    }
+
+    for stmt in body {
+      self.statement(stmt);
+    }
+
+    // No need to end the scope here; returning throws away the whole frame.
+    self.emit_return();
+
+    let state = self.states.pop().expect("lost a function state");
+    let mut function = state.function;
+    function.upvalue_count = state.upvalues.len();
+
+    // the closure belongs to the declaration's line, not the body's last one
+    self.see(name);
+
+    let constant = self.make_constant(Constant::Function(Rc::new(function)));
+    self.emit_op(OpCode::Closure);
+    self.emit_u16(constant);
+
+    for upvalue in state.upvalues {
+      self.emit_byte(upvalue.is_local as u8);
+      self.emit_byte(upvalue.index);
+    }
+  }
+
+  fn class(&mut self, name: &Token, superclass: Option<&Expr>, methods: &[Stmt]) {
+    self.see(name);
+
+    let name_constant = self.identifier_constant(name);
+    let global = self.declare_variable(name);
+
+    self.emit_op(OpCode::Class);
+    self.emit_u16(name_constant);
+    self.define_variable(global);
+
+    self.classes.push(ClassState {
+      has_superclass: false,
+    });
+
+    if let Some(superclass) = superclass {
+      let Expr::Variable(_, super_name) = superclass else {
+        unreachable!("superclass is always a variable");
+      };
+
+      if super_name.symbol() == name.symbol() {
+        self.error(super_name, "A class can't inherit from itself.");
+      }
+
+      self.named_variable(super_name, None);
+
+      // 'super' lives in its own scope, so each subclass gets its own
+      self.begin_scope();
+      self.add_local(&Token::new(TT::Super, name.span));
+      self.mark_initialized();
+
+      self.named_variable(name, None);
+      self.emit_op(OpCode::Inherit);
+      self.classes.last_mut().unwrap().has_superclass = true;
+    }
+
+    // leave the class on the stack while we attach methods to it
+    self.named_variable(name, None);
+
+    for method in methods {
+      let Stmt::Function(mname, params, body) = method else {
+        unreachable!("non-function method in class {}", name.lexeme());
+      };
+
+      self.see(mname);
+      let constant = self.identifier_constant(mname);
+
+      let kind = if mname.symbol() == Symbol::INIT {
+        FunctionKind::Initializer
+      } else {
+        FunctionKind::Method
+      };
+
+      self.function(mname, params, body, kind);
+      self.emit_op(OpCode::Method);
+      self.emit_u16(constant);
+    }
+
+    self.emit_op(OpCode::Pop);
+
+    if self.classes.pop().is_some_and(|class| class.has_superclass) {
+      self.end_scope();
+    }
+  }
+
+  fn expression(&mut self, expr: &Expr) {
+    match expr {
+      Expr::Literal(lit) => match lit {
+        Literal::Number(n) => self.emit_constant(Constant::Number(*n)),
+        Literal::String(s) => self.emit_constant(Constant::String(s.to_string())),
+        Literal::Boolean(true) => self.emit_op(OpCode::True),
+        Literal::Boolean(false) => self.emit_op(OpCode::False),
+        Literal::Nil => self.emit_op(OpCode::Nil),
+      },
+      Expr::Grouping(e) => self.expression(e),
+      Expr::Unary(op, right) => {
+        self.expression(right);
+        self.see(op);
+
+        match op.kind {
+          TT::Bang => self.emit_op(OpCode::Not),
+          TT::Minus => self.emit_op(OpCode::Negate),
+          _ => unreachable!("bad unary"),
+        }
+      },
+      Expr::Binary(left, op, right) => {
+        self.expression(left);
+        self.expression(right);
+        self.see(op);
+
+        match op.kind {
+          TT::EqualEqual => self.emit_op(OpCode::Equal),
+          TT::BangEqual => self.emit_ops(OpCode::Equal, OpCode::Not),
+          TT::Greater => self.emit_op(OpCode::Greater),
+          TT::GreaterEqual => self.emit_ops(OpCode::Less, OpCode::Not),
+          TT::Less => self.emit_op(OpCode::Less),
+          TT::LessEqual => self.emit_ops(OpCode::Greater, OpCode::Not),
+          TT::Plus => self.emit_op(OpCode::Add),
+          TT::Minus => self.emit_op(OpCode::Subtract),
+          TT::Star => self.emit_op(OpCode::Multiply),
+          TT::Slash => self.emit_op(OpCode::Divide),
+          _ => unreachable!("bad binary"),
+        }
+      },
+      Expr::Logical(left, op, right) => {
+        self.expression(left);
+        self.see(op);
+
+        if op.kind_matches(&TT::Or) {
+          let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+          let end_jump = self.emit_jump(OpCode::Jump);
+
+          self.patch_jump(else_jump);
+          self.emit_op(OpCode::Pop);
+          self.expression(right);
+          self.patch_jump(end_jump);
+        } else {
+          let end_jump = self.emit_jump(OpCode::JumpIfFalse);
+
+          self.emit_op(OpCode::Pop);
+          self.expression(right);
+          self.patch_jump(end_jump);
+        }
+      },
+      Expr::Variable(_, name) => self.named_variable(name, None),
+      Expr::Assign(_, name, value) => self.named_variable(name, Some(value)),
+      Expr::Call(callee, paren, args) => {
+        self.expression(callee);
+
+        for arg in args {
+          self.expression(arg);
+        }
+
+        self.see(paren);
+
+        if args.len() > MAX_ARGS {
+          self.error(paren, "Can't have more than 255 arguments.");
+        }
+
+        self.emit_op(OpCode::Call);
+        self.emit_byte(args.len() as u8);
+      },
+      Expr::Get(object, name) => {
+        self.expression(object);
+        self.see(name);
+
+        let constant = self.identifier_constant(name);
+        self.emit_op(OpCode::GetProperty);
+        self.emit_u16(constant);
+      },
+      Expr::Set(object, name, value) => {
+        self.expression(object);
+        self.expression(value);
+        self.see(name);
+
+        let constant = self.identifier_constant(name);
+        self.emit_op(OpCode::SetProperty);
+        self.emit_u16(constant);
+      },
+      Expr::This(_, keyword) => {
+        self.see(keyword);
+
+        if self.classes.is_empty() {
+          self.error(keyword, "Can't use 'this' outside of a class.");
+          return;
+        }
+
+        self.named_variable(keyword, None);
+      },
+      Expr::Super(_, keyword, method) => {
+        self.see(keyword);
+
+        match self.classes.last() {
+          None => self.error(keyword, "Can't use 'super' outside of a class."),
+          Some(class) if !class.has_superclass => {
+            self.error(keyword, "Can't use 'super' in a class with no superclass.")
+          },
+          _ => (),
+        }
+
+        let constant = self.identifier_constant(method);
+
+        self.named_variable(&Token::new(TT::This, keyword.span), None);
+        self.named_variable(keyword, None);
+        self.emit_op(OpCode::GetSuper);
+        self.emit_u16(constant);
+      },
+    }
+  }
+
+  // Emits a get for the variable, or if we're given a value, a set.
+  fn named_variable(&mut self, name: &Token, value: Option<&Expr>) {
+    let top = self.states.len() - 1;
+
+    let (get_op, set_op, arg) = if let Some(slot) = self.resolve_local(top, name) {
+      (OpCode::GetLocal, OpCode::SetLocal, slot as u16)
+    } else if let Some(idx) = self.resolve_upvalue(top, name) {
+      (OpCode::GetUpvalue, OpCode::SetUpvalue, idx as u16)
+    } else {
+      let constant = self.identifier_constant(name);
+      (OpCode::GetGlobal, OpCode::SetGlobal, constant)
+    };
+
+    let op = match value {
+      Some(value) => {
+        self.expression(value);
+        set_op
+      },
+      None => get_op,
+    };
+
+    self.see(name);
+    self.emit_op(op);
+
+    if op == OpCode::GetGlobal || op == OpCode::SetGlobal {
+      self.emit_u16(arg);
+    } else {
+      self.emit_byte(arg as u8);
+    }
+  }
+
+  fn resolve_local(&mut self, state_idx: usize, name: &Token) -> Option<u8> {
+    let sym = name.symbol();
+    let state = &self.states[state_idx];
+
+    let (slot, local) = state
+      .locals
+      .iter()
+      .enumerate()
+      .rev()
+      .find(|(_, local)| local.name == sym)?;
+
+    if local.depth.is_none() {
+      self.error(name, "Can't read local variable in its own initializer.");
+    }
+
+    Some(slot as u8)
+  }
+
+  fn resolve_upvalue(&mut self, state_idx: usize, name: &Token) -> Option<u8> {
+    if state_idx == 0 {
+      return None;
+    }
+
+    if let Some(slot) = self.resolve_local(state_idx - 1, name) {
+      self.states[state_idx - 1].locals[slot as usize].is_captured = true;
+      return Some(self.add_upvalue(state_idx, name, slot, true));
+    }
+
+    let idx = self.resolve_upvalue(state_idx - 1, name)?;
+    Some(self.add_upvalue(state_idx, name, idx, false))
+  }
+
+  fn add_upvalue(
+    &mut self,
+    state_idx: usize,
+    name: &Token,
+    index: u8,
+    is_local: bool,
+  ) -> u8 {
+    let upvalue = Upvalue { index, is_local };
+    let upvalues = &mut self.states[state_idx].upvalues;
+
+    if let Some(existing) = upvalues.iter().position(|uv| *uv == upvalue) {
+      return existing as u8;
+    }
+
+    if upvalues.len() == MAX_UPVALUES {
+      self.error(name, "Too many closure variables in function.");
+      return 0;
+    }
+
+    upvalues.push(upvalue);
+    (upvalues.len() - 1) as u8
+  }
+
+  // Declares the variable in the current scope, and returns the constant
+  // holding its name if it's a global (otherwise, zero, which is ignored).
+  fn declare_variable(&mut self, name: &Token) -> u16 {
+    if self.state().scope_depth == 0 {
+      return self.identifier_constant(name);
+    }
+
+    let sym = name.symbol();
+    let state = self.state();
+
+    let already_declared = state
+      .locals
+      .iter()
+      .rev()
+      .take_while(|local| local.depth.is_none_or(|d| d >= state.scope_depth))
+      .any(|local| local.name == sym);
+
+    if already_declared {
+      self.error(name, "Already a variable with this name in this scope.");
+    }
+
+    self.add_local(name);
+    0
+  }
+
+  fn define_variable(&mut self, global: u16) {
+    if self.state().scope_depth > 0 {
+      self.mark_initialized();
+      return;
+    }
+
+    self.emit_op(OpCode::DefineGlobal);
+    self.emit_u16(global);
+  }
+
+  fn add_local(&mut self, name: &Token) {
+    if self.state().locals.len() == MAX_LOCALS {
+      self.error(name, "Too many local variables in function.");
+      return;
+    }
+
+    self.state_mut().locals.push(Local {
+      name: name.symbol(),
+      depth: None,
+      is_captured: false,
+    });
+  }
+
+  fn mark_initialized(&mut self) {
+    let state = self.state_mut();
+
+    if state.scope_depth == 0 {
+      return;
+    }
+
+    if let Some(local) = state.locals.last_mut() {
+      local.depth = Some(state.scope_depth);
+    }
+  }
+
+  fn begin_scope(&mut self) {
+    self.state_mut().scope_depth += 1;
+  }
+
+  fn end_scope(&mut self) {
+    self.state_mut().scope_depth -= 1;
+
+    loop {
+      let state = self.state();
+      let Some(local) = state.locals.last() else {
+        break;
+      };
+
+      if local.depth.is_some_and(|d| d <= state.scope_depth) {
+        break;
+      }
+
+      let op = if local.is_captured {
+        OpCode::CloseUpvalue
+      } else {
+        OpCode::Pop
+      };
+
+      self.emit_op(op);
+      self.state_mut().locals.pop();
+    }
+  }
+
+  // emitting helpers
+  fn state(&self) -> &FunctionState {
+    self.states.last().expect("no function being compiled")
+  }
+
+  fn state_mut(&mut self) -> &mut FunctionState {
+    self.states.last_mut().expect("no function being compiled")
+  }
+
+  fn chunk(&mut self) -> &mut Chunk {
+    &mut self.state_mut().function.chunk
+  }
+
+  fn see(&mut self, token: &Token) {
+    self.current = token.clone();
+  }
+
+  fn emit_byte(&mut self, byte: u8) {
+    let line = self.current.line();
+    self.chunk().write(byte, line);
+  }
+
+  fn emit_op(&mut self, op: OpCode) {
+    self.emit_byte(op as u8);
+  }
+
+  fn emit_ops(&mut self, first: OpCode, second: OpCode) {
+    self.emit_op(first);
+    self.emit_op(second);
+  }
+
+  fn emit_u16(&mut self, val: u16) {
+    let [hi, lo] = val.to_be_bytes();
+    self.emit_byte(hi);
+    self.emit_byte(lo);
+  }
+
+  fn emit_return(&mut self) {
+    if self.state().kind == FunctionKind::Initializer {
+      self.emit_op(OpCode::GetLocal);
+      self.emit_byte(0);
+    } else {
+      self.emit_op(OpCode::Nil);
+    }
+
+    self.emit_op(OpCode::Return);
+  }
+
+  fn emit_constant(&mut self, constant: Constant) {
+    let idx = self.make_constant(constant);
+    self.emit_op(OpCode::Constant);
+    self.emit_u16(idx);
+  }
+
+  fn make_constant(&mut self, constant: Constant) -> u16 {
+    let idx = self.chunk().add_constant(constant);
+
+    match u16::try_from(idx) {
+      Ok(idx) => idx,
+      Err(_) => {
+        let tok = self.current.clone();
+        self.error(&tok, "Too many constants in one chunk.");
+        0
+      },
+    }
+  }
+
+  fn identifier_constant(&mut self, name: &Token) -> u16 {
+    // reuse the name if we've already got it, since these repeat a lot
+    let lexeme = name.lexeme();
+    let existing = self
+      .chunk()
+      .constants
+      .iter()
+      .position(|c| matches!(c, Constant::String(s) if *s == lexeme));
+
+    match existing {
+      Some(idx) => idx as u16,
+      None => self.make_constant(Constant::String(lexeme)),
+    }
+  }
+
+  fn emit_jump(&mut self, op: OpCode) -> usize {
+    self.emit_op(op);
+    self.emit_u16(u16::MAX);
+    self.chunk().code.len() - 2
+  }
+
+  fn patch_jump(&mut self, offset: usize) {
+    // -2 to account for the jump offset itself
+    let jump = self.chunk().code.len() - offset - 2;
+
+    let Ok(jump) = u16::try_from(jump) else {
+      let tok = self.current.clone();
+      self.error(&tok, "Too much code to jump over.");
+      return;
+    };
+
+    let [hi, lo] = jump.to_be_bytes();
+    let code = &mut self.chunk().code;
+    code[offset] = hi;
+    code[offset + 1] = lo;
+  }
+
+  fn emit_loop(&mut self, loop_start: usize) {
+    self.emit_op(OpCode::Loop);
+
+    // +2 to account for the loop offset itself
+    let offset = self.chunk().code.len() - loop_start + 2;
+
+    match u16::try_from(offset) {
+      Ok(offset) => self.emit_u16(offset),
+      Err(_) => {
+        let tok = self.current.clone();
+        self.error(&tok, "Loop body too large.");
+      },
+    }
+  }
+
+  fn error(&mut self, token: &Token, msg: &str) {
+    self.errors.push(Error::Compile(token.clone(), msg.to_string()));
+  }
+}
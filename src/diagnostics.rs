@@ -0,0 +1,90 @@
+use std::fmt::Write;
+
+use crate::Error;
+
+const RED: &str = "\x1b[1;31m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+// Renders an error the way a compiler would: the message, where it happened,
+// and the line of source it happened on, with the offending bit underlined.
+//
+//   error: unary minus only applicable to numbers
+//    --> script.lox:3:9
+//     |
+//   3 |   print -"x";
+//     |         ^
+//
+// The VM only knows lines, so its errors get the line but no underline, and
+// errors that don't point anywhere in the source just come out as they are.
+// Errors from inside function calls get their backtrace tacked on the end,
+// and a bunch of errors found all at once just get rendered one by one.
+pub fn render(err: &Error, name: &str, source: &str, color: bool) -> String {
+  let (msg, line, span): (&str, _, _) = match err {
+    Error::Invalid(errors) => {
+      let rendered: Vec<_> =
+        errors.iter().map(|err| render(err, name, source, color)).collect();
+      return rendered.join("\n");
+    },
+    Error::Traced(err, trace) => {
+      return format!("{}\n{}", render(err, name, source, color), trace.join("\n"))
+    },
+    Error::Scan(span, msg) => (msg, span.line, Some(*span)),
+    Error::Parse(token, msg)
+    | Error::Resolve(token, msg)
+    | Error::Compile(token, msg)
+    | Error::Runtime(token, msg) => (msg, token.line(), Some(token.span)),
+    Error::OutOfSteps(token)
+    | Error::Interrupted(token)
+    | Error::StackOverflow(token) => {
+      (err.halt_message().unwrap_or_default(), token.line(), Some(token.span))
+    },
+    Error::VmRuntime(line, msg) => (msg, *line, None),
+    _ => return err.to_string(),
+  };
+
+  let paint = |style: &str, text: &str| {
+    if color {
+      format!("{style}{text}{RESET}")
+    } else {
+      text.to_string()
+    }
+  };
+
+  let text = source.lines().nth(line.saturating_sub(1)).unwrap_or("");
+  let gutter = " ".repeat(line.to_string().len());
+
+  let location = match span {
+    Some(span) => format!("{name}:{line}:{}", span.column),
+    None => format!("{name}:{line}"),
+  };
+
+  let mut out = String::new();
+  writeln!(out, "{}{}", paint(RED, "error"), paint(BOLD, &format!(": {msg}"))).unwrap();
+  writeln!(out, "{gutter}{} {location}", paint(BLUE, "-->")).unwrap();
+  writeln!(out, "{gutter} {}", paint(BLUE, "|")).unwrap();
+  write!(out, "{} {text}", paint(BLUE, &format!("{line} |"))).unwrap();
+
+  if let Some(span) = span {
+    // Line the carets up under the span, copying tabs so that they take up
+    // the same room as they do in the line itself. Anything running on past
+    // the end of the line only gets underlined up to there.
+    let before = span.column.saturating_sub(1);
+    let padding: String = text
+      .chars()
+      .take(before)
+      .map(|c| if c == '\t' { '\t' } else { ' ' })
+      .collect();
+
+    let width = source
+      .get(span.start..span.end)
+      .map_or(1, |s| s.lines().next().unwrap_or("").chars().count())
+      .max(1);
+
+    let carets = "^".repeat(width);
+    write!(out, "\n{gutter} {} {padding}{}", paint(BLUE, "|"), paint(RED, &carets)).unwrap();
+  }
+
+  out
+}
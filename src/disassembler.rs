@@ -0,0 +1,141 @@
+use std::fmt::Write;
+
+use crate::chunk::{Chunk, Constant, Function, OpCode};
+
+// Disassembles a compiled function and everything nested inside it, outermost
+// first, in the order they appear in the constant pools.
+pub fn disassemble_function(function: &Function) -> String {
+  let mut out = disassemble_chunk(&function.chunk, &function.display_name());
+
+  for constant in &function.chunk.constants {
+    if let Constant::Function(inner) = constant {
+      out.push('\n');
+      out.push_str(&disassemble_function(inner));
+    }
+  }
+
+  out
+}
+
+pub fn disassemble_chunk(chunk: &Chunk, name: &str) -> String {
+  let mut out = format!("== {name} ==\n");
+  let mut offset = 0;
+
+  while offset < chunk.code.len() {
+    let (text, next) = disassemble_instruction(chunk, offset);
+    out.push_str(&text);
+    out.push('\n');
+    offset = next;
+  }
+
+  out
+}
+
+// Returns the text for the instruction at offset (possibly several lines, for
+// closures), and the offset of the next instruction.
+pub fn disassemble_instruction(chunk: &Chunk, offset: usize) -> (String, usize) {
+  let mut out = format!("{offset:04} ");
+
+  let line = chunk.line_at(offset);
+  if offset > 0 && line == chunk.line_at(offset - 1) {
+    out.push_str("   | ");
+  } else {
+    write!(out, "{line:4} ").unwrap();
+  }
+
+  let byte = chunk.code[offset];
+  let Ok(op) = OpCode::try_from(byte) else {
+    write!(out, "unknown opcode {byte}").unwrap();
+    return (out, offset + 1);
+  };
+
+  let name = op_name(op);
+
+  let next = match op {
+    OpCode::Constant
+    | OpCode::GetGlobal
+    | OpCode::DefineGlobal
+    | OpCode::SetGlobal
+    | OpCode::GetProperty
+    | OpCode::SetProperty
+    | OpCode::GetSuper
+    | OpCode::Class
+    | OpCode::Method => {
+      let idx = chunk.read_u16(offset + 1) as usize;
+      write!(out, "{name:<16} {idx:4} '{}'", constant_at(chunk, idx)).unwrap();
+      offset + 3
+    },
+
+    OpCode::GetLocal
+    | OpCode::SetLocal
+    | OpCode::GetUpvalue
+    | OpCode::SetUpvalue
+    | OpCode::Call => {
+      let slot = chunk.code[offset + 1];
+      write!(out, "{name:<16} {slot:4}").unwrap();
+      offset + 2
+    },
+
+    OpCode::Jump | OpCode::JumpIfFalse | OpCode::Loop => {
+      let jump = chunk.read_u16(offset + 1) as usize;
+      let target = if op == OpCode::Loop {
+        offset + 3 - jump
+      } else {
+        offset + 3 + jump
+      };
+
+      write!(out, "{name:<16} {offset:4} -> {target}").unwrap();
+      offset + 3
+    },
+
+    OpCode::Closure => {
+      let idx = chunk.read_u16(offset + 1) as usize;
+      write!(out, "{name:<16} {idx:4} '{}'", constant_at(chunk, idx)).unwrap();
+
+      let upvalue_count = match chunk.constants.get(idx) {
+        Some(Constant::Function(f)) => f.upvalue_count,
+        _ => 0,
+      };
+
+      let mut next = offset + 3;
+
+      for _ in 0..upvalue_count {
+        let kind = if chunk.code[next] == 1 { "local" } else { "upvalue" };
+        let index = chunk.code[next + 1];
+        write!(out, "\n{next:04}    |                     {kind} {index}").unwrap();
+        next += 2;
+      }
+
+      next
+    },
+
+    _ => {
+      out.push_str(&name);
+      offset + 1
+    },
+  };
+
+  (out, next)
+}
+
+fn constant_at(chunk: &Chunk, idx: usize) -> String {
+  match chunk.constants.get(idx) {
+    Some(constant) => constant.to_string(),
+    None => format!("<bad constant {idx}>"),
+  }
+}
+
+// GetLocal -> OP_GET_LOCAL, as in the book
+fn op_name(op: OpCode) -> String {
+  let mut name = String::from("OP");
+
+  for c in format!("{op:?}").chars() {
+    if c.is_ascii_uppercase() {
+      name.push('_');
+    }
+
+    name.push(c.to_ascii_uppercase());
+  }
+
+  name
+}
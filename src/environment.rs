@@ -1,72 +1,150 @@
+use std::cell::RefCell;
 use std::collections::HashMap;
+use std::rc::Rc;
 
+use crate::gc::{self, Trace, Tracer};
+use crate::symbol::Symbol;
 use crate::value::LoxValue;
 use crate::{Error, Result, Token};
 
-type EnvMap = HashMap<String, LoxValue>;
+type EnvMap = HashMap<Symbol, LoxValue>;
 
-// This is a concession to my inability to think coherently about rust
-// lifetimes. We *should* be able to work that out, but I've poked at it for a
-// good long while and can't quite get there.
-//
-// An Environment is a vec of environments in increasing order of specificity,
-// so: [global, outer, inner].
-#[derive(Debug)]
+pub type EnvRef = Rc<RefCell<Environment>>;
+
+// An Environment is a single frame of variables, plus a pointer to the frame
+// that encloses it, so the whole thing forms a chain back up to the globals:
+// inner -> outer -> global. Frames are reference-counted so that functions
+// can hang on to the chain they were declared in (that is, be closures).
+// That makes cycles, so they're tracked by the cycle collector as well.
+#[derive(Debug, Default)]
 pub struct Environment {
-  scopes: Vec<EnvMap>,
+  values: EnvMap,
+  enclosing: Option<EnvRef>,
 }
 
 impl Environment {
-  pub fn new() -> Self {
-    Environment {
-      scopes: vec![HashMap::new()],
-    }
+  pub fn new() -> EnvRef {
+    let env = Rc::new(RefCell::new(Environment::default()));
+    gc::track_env(&env);
+    env
   }
 
-  pub fn push_scope(&mut self) {
-    self.scopes.push(HashMap::new())
+  pub fn new_enclosing(enclosing: &EnvRef) -> EnvRef {
+    let env = Rc::new(RefCell::new(Environment {
+      values: HashMap::new(),
+      enclosing: Some(Rc::clone(enclosing)),
+    }));
+
+    gc::track_env(&env);
+    env
   }
 
-  pub fn pop_scope(&mut self) {
-    if self.scopes.len() <= 1 {
-      panic!("cannot pop last scope!");
+  pub fn define(&mut self, name: Symbol, value: LoxValue) {
+    if self.values.insert(name, value).is_none() {
+      gc::grew(gc::ENTRY_SIZE);
+    }
+  }
+
+  pub fn get(&self, tok: &Token) -> Result<LoxValue> {
+    let name = tok.symbol();
+
+    if let Some(val) = self.values.get(&name) {
+      return Ok(val.clone());
     }
 
-    self.scopes.pop();
+    match &self.enclosing {
+      Some(enclosing) => enclosing.borrow().get(tok),
+      None => Err(Error::Runtime(
+        tok.clone(),
+        format!("undefined variable '{name}'."),
+      )),
+    }
   }
 
-  pub fn define(&mut self, name: &str, value: LoxValue) {
-    self.scopes.last_mut().unwrap().insert(name.into(), value);
+  // Everything defined in this frame (and not its ancestors).
+  pub fn bindings(&self) -> Vec<(Symbol, LoxValue)> {
+    self
+      .values
+      .iter()
+      .map(|(&name, value)| (name, value.clone()))
+      .collect()
   }
 
-  pub fn get(&self, tok: &Token) -> Result<LoxValue> {
-    let name = tok.lexeme();
+  // Only looks in this frame, for when we know just where a name should be.
+  pub fn get_here(&self, name: Symbol) -> Option<LoxValue> {
+    self.values.get(&name).cloned()
+  }
 
-    for scope in self.scopes.iter().rev() {
-      if let Some(val) = scope.get(&name) {
-        return Ok((*val).clone());
-      }
+  // These are for variables the resolver has found for us, so they only ever
+  // look in the one frame that's `distance` hops up the chain.
+  pub fn get_at(env: &EnvRef, distance: usize, tok: &Token) -> Result<LoxValue> {
+    let name = tok.symbol();
+
+    match Self::ancestor(env, distance).borrow().values.get(&name) {
+      Some(val) => Ok(val.clone()),
+      None => Err(Error::Runtime(
+        tok.clone(),
+        format!("undefined variable '{name}'."),
+      )),
     }
+  }
 
-    Err(Error::Runtime(
-      tok.clone(),
-      format!("undefined variable '{name}'."),
-    ))
+  pub fn assign_at(
+    env: &EnvRef,
+    distance: usize,
+    tok: &Token,
+    new_value: LoxValue,
+  ) -> Result<()> {
+    let ancestor = Self::ancestor(env, distance);
+    ancestor.borrow_mut().define(tok.symbol(), new_value);
+    Ok(())
+  }
+
+  pub fn ancestor(env: &EnvRef, distance: usize) -> EnvRef {
+    let mut env = Rc::clone(env);
+
+    for _ in 0..distance {
+      let enclosing = env
+        .borrow()
+        .enclosing
+        .clone()
+        .expect("resolver gave us a bogus scope depth");
+      env = enclosing;
+    }
+
+    env
   }
 
   pub fn assign(&mut self, tok: &Token, new_value: LoxValue) -> Result<()> {
-    let name = tok.lexeme();
+    let name = tok.symbol();
 
-    for scope in self.scopes.iter_mut().rev() {
-      if let Some(val) = scope.get_mut(&name) {
-        *val = new_value;
-        return Ok(());
-      }
+    if let Some(val) = self.values.get_mut(&name) {
+      *val = new_value;
+      return Ok(());
     }
 
-    Err(Error::Runtime(
-      tok.clone(),
-      format!("undefined variable '{name}'."),
-    ))
+    match &self.enclosing {
+      Some(enclosing) => enclosing.borrow_mut().assign(tok, new_value),
+      None => Err(Error::Runtime(
+        tok.clone(),
+        format!("undefined variable '{name}'."),
+      )),
+    }
+  }
+}
+
+impl Trace for Environment {
+  fn trace(&self, tracer: &mut Tracer) {
+    if let Some(enclosing) = &self.enclosing {
+      tracer.env(enclosing);
+    }
+
+    for value in self.values.values() {
+      tracer.value(value);
+    }
+  }
+
+  fn size(&self) -> usize {
+    std::mem::size_of::<Self>() + self.values.len() * gc::ENTRY_SIZE
   }
 }
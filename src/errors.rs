@@ -3,6 +3,7 @@ use std::fmt;
 use std::io::Error as IoError;
 
 use crate::value::LoxValue;
+use crate::token::Span;
 use crate::{Token, TokenType};
 
 // I have replaced the error handling in the book with more idiomatic rust.
@@ -10,22 +11,69 @@ use crate::{Token, TokenType};
 #[derive(Debug)]
 pub enum Error {
   Io(IoError),
-  Scan(usize, String),
+  Scan(Span, String),
+  ScanFailed,
   Parse(Token, String),
   ParseFailed,
+  Resolve(Token, String),
+  ResolveFailed,
+  Compile(Token, String),
+  CompileFailed,
+  // everything that was wrong with some code that never got to run
+  Invalid(Vec<Error>),
   Return(LoxValue), // not a real error, but you dance with who brung you
   Runtime(Token, String),
+  // a runtime error that got out of one or more function calls, with the
+  // backtrace lines for them, innermost first
+  Traced(Box<Error>, Vec<String>),
+  // the interpreter was stopped, either by running out of its step budget,
+  // by someone setting its interrupt flag, or by a call going deeper than it
+  // allows; the token is where it was
+  OutOfSteps(Token),
+  Interrupted(Token),
+  StackOverflow(Token),
+  VmRuntime(usize, String),
+  Bytecode(String),
   TryFrom(String),
 }
 
 impl Error {
+  // where in the source this happened, if we know
+  pub fn span(&self) -> Option<Span> {
+    match self {
+      Error::Scan(span, _) => Some(*span),
+      Error::Parse(token, _)
+      | Error::Resolve(token, _)
+      | Error::Compile(token, _)
+      | Error::Runtime(token, _) => Some(token.span),
+      Error::OutOfSteps(token)
+      | Error::Interrupted(token)
+      | Error::StackOverflow(token) => Some(token.span),
+      Error::Traced(err, _) => err.span(),
+      _ => None,
+    }
+  }
+
+  // what to say about an error that stopped the interpreter
+  pub fn halt_message(&self) -> Option<&'static str> {
+    match self {
+      Error::OutOfSteps(_) => Some("Ran out of steps."),
+      Error::Interrupted(_) => Some("Interrupted."),
+      Error::StackOverflow(_) => Some("Stack overflow."),
+      _ => None,
+    }
+  }
+
   fn line_display(&self) -> String {
     match self {
-      Error::Parse(token, msg) | Error::Runtime(token, msg) => {
+      Error::Parse(token, msg)
+      | Error::Resolve(token, msg)
+      | Error::Compile(token, msg)
+      | Error::Runtime(token, msg) => {
         if token.kind == TokenType::EOF {
-          format!("[line {}] Error at end: {msg}", token.line)
+          format!("[line {}] Error at end: {msg}", token.line())
         } else {
-          format!("[line {}] Error at {}: {msg}", token.line, token.lexeme())
+          format!("[line {}] Error at {}: {msg}", token.line(), token.lexeme())
         }
       },
       _ => unimplemented!(),
@@ -47,11 +95,29 @@ impl fmt::Display for Error {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     match self {
       Error::Io(err) => write!(f, "{}", err),
-      Error::Scan(line, msg) => write!(f, "[line {line}] Scan error: {msg}"),
+      Error::Scan(span, msg) => write!(f, "[line {}] Scan error: {msg}", span.line),
       Error::Parse(_, _) => write!(f, "{}", self.line_display()),
+      Error::ScanFailed => write!(f, "scan failed"),
       Error::ParseFailed => write!(f, "parse failed"),
+      Error::Resolve(_, _) => write!(f, "{}", self.line_display()),
+      Error::ResolveFailed => write!(f, "resolve failed"),
+      Error::Compile(_, _) => write!(f, "{}", self.line_display()),
+      Error::CompileFailed => write!(f, "compile failed"),
+      Error::Invalid(errors) => {
+        let lines: Vec<_> = errors.iter().map(Error::to_string).collect();
+        write!(f, "{}", lines.join("\n"))
+      },
       Error::Return(_) => write!(f, "<return>, you should never see this!"),
       Error::Runtime(_, _) => write!(f, "{}", self.line_display()),
+      Error::OutOfSteps(token)
+      | Error::Interrupted(token)
+      | Error::StackOverflow(token) => {
+        let msg = self.halt_message().unwrap_or_default();
+        write!(f, "[line {}] Error: {msg}", token.line())
+      },
+      Error::Traced(err, trace) => write!(f, "{err}\n{}", trace.join("\n")),
+      Error::VmRuntime(line, msg) => write!(f, "[line {line}] Error: {msg}"),
+      Error::Bytecode(msg) => write!(f, "invalid compiled program: {msg}"),
       Error::TryFrom(err) => write!(f, "{}", err),
     }
   }
@@ -1,25 +1,51 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
 use crate::Token;
 
 // This might be totally bananas, but we'll see.
 
+// Rust doesn't give us object identity the way Java does, so rather than the
+// interpreter keeping a side table keyed on expressions, the ones the resolver
+// needs to say something about carry a spot for it to write its answer in:
+// how many environments up the chain the variable lives, or nothing for a
+// global. Keeping it in the tree means it goes away along with the code it's
+// about, and clones of the tree share it.
+#[derive(Debug, Clone, Default)]
+pub struct Depth(Rc<Cell<Option<usize>>>);
+
+impl Depth {
+  pub fn get(&self) -> Option<usize> {
+    self.0.get()
+  }
+
+  pub fn set(&self, depth: usize) {
+    self.0.set(Some(depth));
+  }
+}
+
 #[derive(Debug, Clone)]
 pub enum Literal {
   Number(f64),
-  String(String),
+  String(Rc<str>),
   Boolean(bool),
   Nil,
 }
 
 #[derive(Debug, Clone)]
 pub enum Expr {
-  Assign(Token, Box<Expr>),
+  Assign(Depth, Token, Box<Expr>),
   Binary(Box<Expr>, Token, Box<Expr>),
   Call(Box<Expr>, Token, Vec<Box<Expr>>),
+  Get(Box<Expr>, Token),
   Grouping(Box<Expr>),
   Literal(Literal),
   Logical(Box<Expr>, Token, Box<Expr>),
+  Set(Box<Expr>, Token, Box<Expr>),
+  Super(Depth, Token, Token),
+  This(Depth, Token),
   Unary(Token, Box<Expr>),
-  Variable(Token),
+  Variable(Depth, Token),
 }
 
 // I just want something to be able to stick in to get stuff to compile while
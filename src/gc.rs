@@ -0,0 +1,345 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::{Rc, Weak};
+
+use crate::environment::{EnvRef, Environment};
+use crate::symbol::Symbol;
+use crate::value::{Callable, InstanceRef, LoxClass, LoxInstance, LoxValue};
+
+// The tree-walker's values are all reference-counted, which frees most things
+// as soon as they're done with, but not cycles: a function closes over the
+// environment it's declared in, and if that environment holds the function
+// (which it does, for any function declared in it), neither count ever gets to
+// zero. Classes and their methods' closures go round in circles the same way,
+// and so can instances that point at each other.
+//
+// So this cleans up after the counting, the way Python does. Everything that
+// can be part of a cycle (environments, instances, classes and functions)
+// gets noted here
+// when it's made. To collect, we take each one's reference count and knock off
+// the references the others hold to it; whatever's left must be held from
+// outside, by the interpreter itself or by something on the Rust stack partway
+// through running. Those are the roots, and anything they can reach lives. The
+// rest is only keeping itself alive, so we empty it out, which breaks the
+// cycles and lets the counting do the actual freeing.
+//
+// References we can't see into (a native closure that captured a value, say)
+// just look like they come from outside, so the worst they can do is keep
+// something alive that didn't need to be.
+
+// Knobs for the garbage collectors, the VM's heap and this one alike. Each
+// collects once it has grown past a threshold, and afterwards sets the next
+// threshold to some multiple of whatever survived. In stress mode it collects
+// whenever anything new has been made instead, which is slow but shakes out
+// missing roots quickly.
+#[derive(Debug, Clone, Copy)]
+pub struct GcConfig {
+  pub initial_threshold: usize, // in (approximate) bytes
+  pub grow_factor: usize,
+  pub stress: bool,
+  pub log: bool, // report on each collection to stderr
+}
+
+impl Default for GcConfig {
+  fn default() -> Self {
+    GcConfig {
+      initial_threshold: 1024 * 1024,
+      grow_factor: 2,
+      stress: false,
+      log: false,
+    }
+  }
+}
+
+// roughly how much room a variable, field or method takes up
+pub(crate) const ENTRY_SIZE: usize = std::mem::size_of::<(Symbol, LoxValue)>();
+
+// Everything that's been made since the last collection, plus everything that
+// survived it. These are weak, so being in here doesn't keep anything alive.
+#[derive(Default)]
+struct Tracked {
+  nodes: Vec<WeakNode>,
+  bytes_allocated: usize, // approximately
+  allocations_since_gc: usize,
+}
+
+thread_local! {
+  static TRACKED: RefCell<Tracked> = RefCell::new(Tracked::default());
+}
+
+enum WeakNode {
+  Env(Weak<RefCell<Environment>>),
+  Instance(Weak<RefCell<LoxInstance>>),
+  Class(Weak<LoxClass>),
+  Function(Weak<Callable>),
+}
+
+enum Node {
+  Env(EnvRef),
+  Instance(InstanceRef),
+  Class(Rc<LoxClass>),
+  Function(Rc<Callable>),
+}
+
+// what we've taken out of the garbage, to be dropped once we're done
+type Garbage = Box<dyn std::any::Any>;
+
+// Things the collector keeps track of say what they hold references to, and
+// about how big they are.
+pub(crate) trait Trace {
+  fn trace(&self, tracer: &mut Tracer);
+  fn size(&self) -> usize;
+}
+
+#[derive(Default)]
+pub(crate) struct Tracer {
+  found: Vec<usize>,
+}
+
+// what the collector found out, for logging
+pub(crate) struct Collection {
+  pub freed: usize,
+  pub bytes_before: usize,
+  pub bytes_after: usize,
+}
+
+impl Tracer {
+  pub fn env(&mut self, env: &EnvRef) {
+    self.found.push(address(env));
+  }
+
+  pub fn instance(&mut self, instance: &InstanceRef) {
+    self.found.push(address(instance));
+  }
+
+  pub fn class(&mut self, class: &Rc<LoxClass>) {
+    self.found.push(address(class));
+  }
+
+  pub fn value(&mut self, value: &LoxValue) {
+    match value {
+      LoxValue::Function(callable) => self.found.push(address(callable)),
+      LoxValue::Class(class) => self.class(class),
+      LoxValue::Instance(instance) => self.instance(instance),
+      _ => (),
+    }
+  }
+}
+
+fn address<T>(rc: &Rc<T>) -> usize {
+  Rc::as_ptr(rc) as usize
+}
+
+pub(crate) fn track_env(env: &EnvRef) {
+  track(
+    WeakNode::Env(Rc::downgrade(env)),
+    std::mem::size_of::<Environment>(),
+  );
+}
+
+pub(crate) fn track_instance(instance: &InstanceRef) {
+  track(
+    WeakNode::Instance(Rc::downgrade(instance)),
+    std::mem::size_of::<LoxInstance>(),
+  );
+}
+
+pub(crate) fn track_class(class: &Rc<LoxClass>) {
+  track(WeakNode::Class(Rc::downgrade(class)), class.size());
+}
+
+pub(crate) fn track_function(function: &Rc<Callable>) {
+  track(WeakNode::Function(Rc::downgrade(function)), function.size());
+}
+
+fn track(node: WeakNode, size: usize) {
+  TRACKED.with(|tracked| {
+    let mut tracked = tracked.borrow_mut();
+    tracked.nodes.push(node);
+    tracked.bytes_allocated += size;
+    tracked.allocations_since_gc += 1;
+  });
+}
+
+// for when something we're tracking gets bigger
+pub(crate) fn grew(bytes: usize) {
+  TRACKED.with(|tracked| tracked.borrow_mut().bytes_allocated += bytes);
+}
+
+pub(crate) fn bytes_allocated() -> usize {
+  TRACKED.with(|tracked| tracked.borrow().bytes_allocated)
+}
+
+pub(crate) fn allocations_since_gc() -> usize {
+  TRACKED.with(|tracked| tracked.borrow().allocations_since_gc)
+}
+
+// Finds everything that's only being kept alive by cycles, and breaks them.
+// If anything's in the middle of being changed (which shouldn't happen between
+// statements, but still), we leave it for next time and return None.
+pub(crate) fn collect() -> Option<Collection> {
+  let (weak, bytes_before) = TRACKED.with(|tracked| {
+    let mut tracked = tracked.borrow_mut();
+    (std::mem::take(&mut tracked.nodes), tracked.bytes_allocated)
+  });
+
+  let nodes: Vec<Node> = weak.iter().filter_map(WeakNode::upgrade).collect();
+  let index: HashMap<usize, usize> = nodes
+    .iter()
+    .enumerate()
+    .map(|(i, node)| (node.address(), i))
+    .collect();
+
+  // references from outside, once we've taken off our own (just now, in
+  // upgrade) and everything the other nodes have
+  let mut outside: Vec<usize> =
+    nodes.iter().map(|node| node.strong_count() - 1).collect();
+  let mut edges = Vec::with_capacity(nodes.len());
+  let mut sizes = Vec::with_capacity(nodes.len());
+  let mut tracer = Tracer::default();
+
+  for node in &nodes {
+    let Some(size) = node.visit(&mut tracer) else {
+      TRACKED.with(|tracked| tracked.borrow_mut().nodes.extend(weak));
+      return None;
+    };
+
+    let children: Vec<usize> = tracer
+      .found
+      .drain(..)
+      .filter_map(|addr| index.get(&addr).copied())
+      .collect();
+
+    for &child in &children {
+      outside[child] = outside[child].saturating_sub(1);
+    }
+
+    edges.push(children);
+    sizes.push(size);
+  }
+
+  let mut live = vec![false; nodes.len()];
+  let mut pending: Vec<usize> = (0..nodes.len()).filter(|&i| outside[i] > 0).collect();
+
+  for &root in &pending {
+    live[root] = true;
+  }
+
+  while let Some(i) = pending.pop() {
+    for &child in &edges[i] {
+      if !live[child] {
+        live[child] = true;
+        pending.push(child);
+      }
+    }
+  }
+
+  let mut survivors = vec![];
+  let mut garbage = vec![];
+  let mut bytes_after = 0;
+
+  for (i, node) in nodes.iter().enumerate() {
+    let contents = if live[i] { None } else { node.clear() };
+
+    match contents {
+      Some(contents) => garbage.push(contents),
+      None => {
+        survivors.push(node.downgrade());
+        bytes_after += sizes[i];
+      },
+    }
+  }
+
+  let freed = weak.len() - survivors.len();
+
+  TRACKED.with(|tracked| {
+    let mut tracked = tracked.borrow_mut();
+    tracked.nodes.extend(survivors);
+    tracked.bytes_allocated = bytes_after;
+    tracked.allocations_since_gc = 0;
+  });
+
+  // Only now that nothing's borrowed do we let go of the garbage, since
+  // dropping it can drop all sorts of other things.
+  drop(nodes);
+  drop(garbage);
+
+  Some(Collection {
+    freed,
+    bytes_before,
+    bytes_after,
+  })
+}
+
+impl WeakNode {
+  fn upgrade(&self) -> Option<Node> {
+    match self {
+      WeakNode::Env(env) => env.upgrade().map(Node::Env),
+      WeakNode::Instance(instance) => instance.upgrade().map(Node::Instance),
+      WeakNode::Class(class) => class.upgrade().map(Node::Class),
+      WeakNode::Function(function) => function.upgrade().map(Node::Function),
+    }
+  }
+}
+
+impl Node {
+  fn downgrade(&self) -> WeakNode {
+    match self {
+      Node::Env(env) => WeakNode::Env(Rc::downgrade(env)),
+      Node::Instance(instance) => WeakNode::Instance(Rc::downgrade(instance)),
+      Node::Class(class) => WeakNode::Class(Rc::downgrade(class)),
+      Node::Function(function) => WeakNode::Function(Rc::downgrade(function)),
+    }
+  }
+
+  fn address(&self) -> usize {
+    match self {
+      Node::Env(env) => address(env),
+      Node::Instance(instance) => address(instance),
+      Node::Class(class) => address(class),
+      Node::Function(function) => address(function),
+    }
+  }
+
+  fn strong_count(&self) -> usize {
+    match self {
+      Node::Env(env) => Rc::strong_count(env),
+      Node::Instance(instance) => Rc::strong_count(instance),
+      Node::Class(class) => Rc::strong_count(class),
+      Node::Function(function) => Rc::strong_count(function),
+    }
+  }
+
+  // traces the node, and hands back its size, unless it's borrowed
+  fn visit(&self, tracer: &mut Tracer) -> Option<usize> {
+    match self {
+      Node::Env(env) => visit(&*env.try_borrow().ok()?, tracer),
+      Node::Instance(instance) => visit(&*instance.try_borrow().ok()?, tracer),
+      Node::Class(class) => visit(class.as_ref(), tracer),
+      Node::Function(function) => visit(function.as_ref(), tracer),
+    }
+  }
+
+  // Empties out an environment or instance, handing back what was in it, or
+  // None if we can't get at it right now. Classes and functions don't need
+  // emptying: any cycle through one goes through a closure too, and emptying
+  // that does the job.
+  fn clear(&self) -> Option<Garbage> {
+    match self {
+      Node::Env(env) => {
+        let mut env = env.try_borrow_mut().ok()?;
+        Some(Box::new(std::mem::take(&mut *env)))
+      },
+      Node::Instance(instance) => {
+        let mut instance = instance.try_borrow_mut().ok()?;
+        Some(Box::new(instance.take_fields()))
+      },
+      Node::Class(_) | Node::Function(_) => Some(Box::new(())),
+    }
+  }
+}
+
+fn visit(node: &impl Trace, tracer: &mut Tracer) -> Option<usize> {
+  node.trace(tracer);
+  Some(node.size())
+}
@@ -1,25 +1,98 @@
+mod embed;
 mod globals;
+mod output;
 
-use crate::environment::Environment;
-use crate::expr::Expr;
+use std::collections::HashMap;
+use std::io::Write;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use crate::environment::{EnvRef, Environment};
+use crate::expr::{Depth, Expr};
+use crate::gc::{self, GcConfig};
 use crate::stmt::Stmt;
-use crate::value::LoxValue;
-use crate::{Error, Result, Token, TokenType as TT};
+use crate::symbol::Symbol;
+use crate::value::{LoxClass, LoxFunction, LoxInstance, LoxValue};
+use crate::{Error, Result, Token, TokenType as TT, MAX_CALL_DEPTH};
+pub use embed::{NativeArg, NativeFn};
+pub use output::Output;
+
+// How much of the Rust stack calls can use between them, on top of the limit
+// on how deep they nest (MAX_CALL_DEPTH, unless told otherwise). Each Lox call
+// takes a good few Rust frames, and how big those are depends on the build
+// and on how deeply the function's body nests, so counting calls alone can't
+// keep us inside the stack. This leaves room to spare on a 2 MiB thread (what
+// Rust gives spawned threads, and tests, by default) even in a debug build.
+const MAX_STACK: usize = 1024 * 1024;
 
 #[derive(Debug)]
 pub struct Interpreter {
-  env: Environment,
+  globals: EnvRef,
+  env: EnvRef,
+  frames: Vec<CallFrame>,
+  output: Output,
+  steps_left: Option<u64>, // None if there's no limit
+  max_call_depth: usize,
+  max_stack: usize,  // bytes
+  stack_base: usize, // roughly where the stack was when the outermost call began
+  interrupt: Option<Arc<AtomicBool>>,
+  gc: GcConfig,
+  next_gc: usize, // collect cycles once there's more than this many bytes
+}
+
+// One function call in progress: who was called, and the line it was called
+// from.
+#[derive(Debug)]
+struct CallFrame {
+  name: Rc<str>,
+  line: usize,
+}
+
+impl Default for Interpreter {
+  fn default() -> Self {
+    Self::new()
+  }
 }
 
 impl Interpreter {
   pub fn new() -> Self {
-    let mut int = Interpreter {
-      env: Environment::new(),
-    };
+    Interpreter::with_output(std::io::stdout())
+  }
 
-    globals::install_in(&mut int.env);
+  // An interpreter that prints to the given writer, rather than stdout.
+  pub fn with_output(writer: impl Write + 'static) -> Self {
+    let globals = Environment::new();
+    globals::install_in(&mut globals.borrow_mut());
+
+    Interpreter {
+      env: Rc::clone(&globals),
+      globals,
+      frames: vec![],
+      output: Output::new(writer),
+      steps_left: None,
+      max_call_depth: MAX_CALL_DEPTH,
+      max_stack: MAX_STACK,
+      stack_base: 0,
+      interrupt: None,
+      gc: GcConfig::default(),
+      next_gc: GcConfig::default().initial_threshold,
+    }
+  }
 
-    int
+  // Or one that hands everything it prints to a closure, a line at a time.
+  pub fn with_output_fn(func: impl FnMut(&str) + 'static) -> Self {
+    Interpreter::with_output(Output::from_fn(func))
+  }
+
+  // Natives that produce output should write it here, like print does.
+  pub fn output(&mut self) -> &mut Output {
+    &mut self.output
+  }
+
+  // Everything defined at the top level, natives included.
+  pub fn globals(&self) -> Vec<(Symbol, LoxValue)> {
+    self.globals.borrow().bindings()
   }
 
   pub fn interpret(&mut self, statements: Vec<Stmt>) -> Result<()> {
@@ -30,58 +103,90 @@ impl Interpreter {
     Ok(())
   }
 
+  // For when the caller wants the value back, rather than a statement's
+  // side effects.
+  pub fn evaluate(&mut self, expr: &Expr) -> Result<LoxValue> {
+    self.eval_expr(expr)
+  }
+
   fn execute(&mut self, stmt: &Stmt) -> Result<()> {
+    if self.should_collect() {
+      self.collect_garbage();
+    }
+
     match stmt {
       Stmt::Empty => (),
       Stmt::Block(block) => {
-        self.env.push_scope();
-        self.execute_block(block)?;
-        self.env.pop_scope();
+        self.execute_block(block, Environment::new_enclosing(&self.env))?;
       },
       Stmt::Expression(e) => {
         self.eval_expr(e)?;
       },
       Stmt::Print(e) => {
         let val = self.eval_expr(e)?;
-        println!("{}", val);
+        writeln!(self.output, "{}", val)?;
       },
       Stmt::Var(name, init) => {
         let value = self.eval_expr(init)?;
-        self.env.define(name, value);
+        self.env.borrow_mut().define(name.symbol(), value);
       },
       Stmt::Function(name, params, body) => {
-        // We have to clone here to appease the borrow checker, because I
-        // haven't structured things in such a way that it can tell the func
-        // won't outlive the lifetime of our environment.
-        let pclone = params.clone();
-        let bclone = body.clone();
-
+        // The environment we were declared in is shared, so that the function
+        // closes over it rather than over whatever happens to be live at call
+        // time.
         let func =
-          move |interp: &mut Interpreter, args: Vec<LoxValue>| -> Result<LoxValue> {
-            interp.env.push_scope();
-
-            for (idx, param) in pclone.iter().enumerate() {
-              interp.env.define(
-                &param.lexeme(),
-                args.get(idx).expect("arity mistake").clone(),
-              );
-            }
+          LoxFunction::new(name.lexeme(), params, body, Rc::clone(&self.env), false);
 
-            let ret = match interp.execute_block(&bclone) {
-              Ok(()) => LoxValue::Nil,
-              Err(Error::Return(retval)) => retval,
-              Err(e) => return Err(e),
-            };
+        self.env.borrow_mut().define(name.symbol(), func.into_value());
+      },
+      Stmt::Class(name, superclass, methods) => {
+        let superclass = match superclass {
+          Some(expr) => match self.eval_expr(expr)? {
+            LoxValue::Class(class) => Some(class),
+            _ => {
+              let Expr::Variable(_, super_name) = expr.as_ref() else {
+                unreachable!("superclass is always a variable");
+              };
+
+              return Err(Error::Runtime(
+                super_name.clone(),
+                "Superclass must be a class.".into(),
+              ));
+            },
+          },
+          None => None,
+        };
+
+        // Methods in a subclass close over an extra environment holding
+        // 'super', which sits between the class and its declaring scope.
+        let enclosing = Rc::clone(&self.env);
+
+        if let Some(ref superclass) = superclass {
+          self.env = Environment::new_enclosing(&enclosing);
+          self
+            .env
+            .borrow_mut()
+            .define(Symbol::SUPER, LoxValue::Class(Rc::clone(superclass)));
+        }
 
-            interp.env.pop_scope();
+        let mut class_methods = HashMap::new();
 
-            Ok(ret)
+        for method in methods {
+          let Stmt::Function(mname, params, body) = method else {
+            unreachable!("non-function method in class {}", name.lexeme());
           };
 
-        let callable =
-          LoxValue::new_callable(name.lexeme(), params.len(), Box::new(func));
+          let is_init = mname.symbol() == Symbol::INIT;
+          let func =
+            LoxFunction::new(mname.lexeme(), params, body, Rc::clone(&self.env), is_init);
 
-        self.env.define(&name.lexeme(), callable);
+          class_methods.insert(mname.symbol(), func);
+        }
+
+        self.env = enclosing;
+
+        let class = LoxClass::new(name.lexeme(), superclass, class_methods);
+        self.env.borrow_mut().define(name.symbol(), LoxValue::Class(class));
       },
 
       // control flow
@@ -93,17 +198,22 @@ impl Interpreter {
         }
       },
 
-      Stmt::While(cond, body) => {
+      Stmt::While(keyword, cond, body) => {
         // This cloning sorta stinks, but I wrote this such that eval consumes
         // the expr. I should reconsider that, maybe, but it wasn't trivially
         // doable, so let's get this working first.
         while self.eval_expr(cond)?.is_truthy() {
+          self.step(keyword)?;
           self.execute(body)?;
         }
       },
 
       Stmt::Return(_tok, expr) => {
-        let value = self.eval_expr(expr)?;
+        let value = match expr {
+          Some(expr) => self.eval_expr(expr)?,
+          None => LoxValue::Nil,
+        };
+
         return Err(Error::Return(value));
       },
     };
@@ -111,45 +221,52 @@ impl Interpreter {
     Ok(())
   }
 
-  fn execute_block(&mut self, block: &Vec<Stmt>) -> Result<()> {
-    // Possibly, I should do something to restore the scope if executing the
-    // statement fails, but in reality we're going to propogate that all the
-    // way up the stack and tear down anyway, so let's just not bother for
-    // now.
-    for statement in block {
-      self.execute(statement)?;
-    }
+  // Executes the block in the given environment, restoring the current one
+  // afterwards. We have to restore it even if something fails, because a
+  // return is implemented as an error, and that doesn't tear anything down.
+  pub(crate) fn execute_block(&mut self, block: &[Stmt], env: EnvRef) -> Result<()> {
+    let previous = std::mem::replace(&mut self.env, env);
 
-    Ok(())
+    let result = block.iter().try_for_each(|statement| self.execute(statement));
+
+    self.env = previous;
+    result
   }
 
-  fn eval_expr(&mut self, expr: &Box<Expr>) -> Result<LoxValue> {
-    let val = match expr.as_ref() {
+  fn eval_expr(&mut self, expr: &Expr) -> Result<LoxValue> {
+    let val = match expr {
       Expr::Literal(val) => val.clone().into(),
-      Expr::Grouping(e) => self.eval_expr(&e)?,
+      Expr::Grouping(e) => self.eval_expr(e)?,
       Expr::Unary(ref op, ref right) => self.eval_unary_expr(op, right)?,
       Expr::Binary(ref left, ref op, ref right) => {
         self.eval_binary_expr(left, op, right)?
       },
-      Expr::Variable(ref token) => self.env.get(token)?,
-      Expr::Assign(token, expr) => {
-        let value = self.eval_expr(&expr)?;
-        self.env.assign(&token, value.clone())?;
+      Expr::Variable(depth, token) => self.look_up_variable(depth, token)?,
+      Expr::Assign(depth, token, expr) => {
+        let value = self.eval_expr(expr)?;
+
+        match depth.get() {
+          Some(depth) => {
+            Environment::assign_at(&self.env, depth, token, value.clone())?
+          },
+          None => self.globals.borrow_mut().assign(token, value.clone())?,
+        };
+
         value
       },
       Expr::Logical(left, op, right) => {
-        let left_val = self.eval_expr(&left)?;
+        let left_val = self.eval_expr(left)?;
         let left_true = left_val.is_truthy();
 
         if op.kind_matches(&TT::Or) {
           if left_true {
             left_val
           } else {
-            self.eval_expr(&right)?
+            self.eval_expr(right)?
           }
         } else {
           if left_true {
-            self.eval_expr(&right)?
+            self.eval_expr(right)?
           } else {
             left_val
           }
@@ -165,11 +282,21 @@ impl Interpreter {
           ));
         }
 
-        let func = callee.as_callable();
-        if args.len() != func.arity {
+        let (name, min_arity, max_arity, signature) = match &callee {
+          LoxValue::Class(class) => {
+            (class.name.clone(), class.arity(), Some(class.arity()), None)
+          },
+          _ => {
+            let callable = callee.as_callable();
+            let (min, max) = (callable.min_arity, callable.max_arity);
+            (callable.name.clone(), min, max, callable.signature())
+          },
+        };
+
+        if args.len() < min_arity || max_arity.is_some_and(|max| args.len() > max) {
           return Err(Error::Runtime(
             paren.clone(),
-            format!("Expected {} arguments but got {}.", func.arity, args.len()),
+            arity_mismatch(min_arity, max_arity, args.len()),
           ));
         }
 
@@ -178,21 +305,196 @@ impl Interpreter {
           arguments.push(self.eval_expr(arg)?);
         }
 
-        func.call(self, arguments)?
+        if let Some(signature) = signature {
+          signature
+            .check_types(&name, &arguments)
+            .map_err(|msg| Error::Runtime(paren.clone(), msg))?;
+        }
+
+        self.step(paren)?;
+
+        let here = stack_address();
+        if self.frames.is_empty() {
+          self.stack_base = here;
+        }
+
+        if self.frames.len() >= self.max_call_depth
+          || self.stack_base.abs_diff(here) > self.max_stack
+        {
+          return Err(Error::StackOverflow(paren.clone()));
+        }
+
+        self.frames.push(CallFrame {
+          name: Rc::clone(&name),
+          line: paren.line(),
+        });
+
+        let result = match &callee {
+          LoxValue::Class(class) => LoxClass::instantiate(class, self, arguments),
+          _ => callee.as_callable().call(self, arguments),
+        };
+
+        // Natives whose arguments wouldn't convert get the blame put on the
+        // call, just like ones whose signature turned the arguments away: it's
+        // an error in the calling code, and gets traced (or not) as one.
+        let result = result.map_err(|err| match err {
+          Error::TryFrom(msg) => Error::Runtime(paren.clone(), format!("{name} {msg}")),
+          err => self.trace(err),
+        });
+        self.frames.pop();
+        result?
+      },
+      Expr::Get(object, name) => match self.eval_expr(object)? {
+        LoxValue::Instance(instance) => LoxInstance::get(&instance, name)?,
+        _ => {
+          return Err(Error::Runtime(
+            name.clone(),
+            "Only instances have properties.".into(),
+          ))
+        },
+      },
+      Expr::Set(object, name, value) => {
+        let LoxValue::Instance(instance) = self.eval_expr(object)? else {
+          return Err(Error::Runtime(
+            name.clone(),
+            "Only instances have fields.".into(),
+          ));
+        };
+
+        let value = self.eval_expr(value)?;
+        instance.borrow_mut().set(name, value.clone());
+        value
+      },
+      Expr::Super(depth, keyword, method) => {
+        self.eval_super_expr(depth, keyword, method)?
       },
+      Expr::This(depth, keyword) => self.look_up_variable(depth, keyword)?,
     };
 
     Ok(val)
   }
 
-  fn eval_unary_expr(&mut self, op: &Token, right: &Box<Expr>) -> Result<LoxValue> {
+  // Every call and every time around a loop is a step. This is where we stop,
+  // if we've used up our budget of them, or if we've been asked to.
+  fn step(&mut self, token: &Token) -> Result<()> {
+    if self
+      .interrupt
+      .as_ref()
+      .is_some_and(|flag| flag.load(Ordering::Relaxed))
+    {
+      return Err(Error::Interrupted(token.clone()));
+    }
+
+    match &mut self.steps_left {
+      Some(0) => Err(Error::OutOfSteps(token.clone())),
+      Some(steps) => {
+        *steps -= 1;
+        Ok(())
+      },
+      None => Ok(()),
+    }
+  }
+
+  // Same knobs as the VM's collector: past a threshold (or, in stress mode,
+  // whenever anything new has been made since last time), and only ever
+  // between statements.
+  fn should_collect(&self) -> bool {
+    if self.gc.stress {
+      gc::allocations_since_gc() > 0
+    } else {
+      gc::bytes_allocated() > self.next_gc
+    }
+  }
+
+  fn collect_garbage(&mut self) {
+    let Some(collection) = gc::collect() else {
+      return;
+    };
+
+    let grown = collection.bytes_after * self.gc.grow_factor;
+    self.next_gc = grown.max(self.gc.initial_threshold);
+
+    if self.gc.log {
+      eprintln!(
+        "-- gc: freed {} objects, {} -> {} bytes, next at {}",
+        collection.freed, collection.bytes_before, collection.bytes_after, self.next_gc,
+      );
+    }
+  }
+
+  // Attaches a backtrace to a runtime error that's on its way out of the
+  // innermost call, book-style:
+  //
+  //   [line 3] in foo()
+  //   [line 7] in script
+  //
+  // Each frame knows the line it was called from, which is the line the
+  // frame outside it was on. Errors that have a trace already just pass.
+  fn trace(&self, err: Error) -> Error {
+    let Error::Runtime(ref token, _) = err else {
+      return err;
+    };
+
+    let lines = std::iter::once(token.line())
+      .chain(self.frames.iter().rev().map(|frame| frame.line));
+
+    let names = self
+      .frames
+      .iter()
+      .rev()
+      .map(|frame| format!("{}()", frame.name))
+      .chain(["script".to_string()]);
+
+    let trace = lines.zip(names).map(|(line, name)| format!("[line {line}] in {name}"));
+    Error::Traced(Box::new(err), trace.collect())
+  }
+
+  fn look_up_variable(&self, depth: &Depth, token: &Token) -> Result<LoxValue> {
+    match depth.get() {
+      Some(depth) => Environment::get_at(&self.env, depth, token),
+      None => self.globals.borrow().get(token),
+    }
+  }
+
+  fn eval_super_expr(
+    &self,
+    depth: &Depth,
+    keyword: &Token,
+    method: &Token,
+  ) -> Result<LoxValue> {
+    let depth = depth.get().expect("resolver always resolves 'super'");
+
+    let superclass = Environment::get_at(&self.env, depth, keyword)?;
+    let LoxValue::Class(superclass) = superclass else {
+      unreachable!("'super' is always a class");
+    };
+
+    // 'this' is always in the environment just inside the one with 'super'
+    let this = Environment::ancestor(&self.env, depth - 1)
+      .borrow()
+      .get_here(Symbol::THIS);
+
+    let Some(LoxValue::Instance(instance)) = this else {
+      unreachable!("'this' is always an instance");
+    };
+
+    match superclass.find_method(method.symbol()) {
+      Some(func) => Ok(func.bind(&instance).into_value()),
+      None => Err(Error::Runtime(
+        method.clone(),
+        format!("Undefined property '{}'.", method.lexeme()),
+      )),
+    }
+  }
+
+  fn eval_unary_expr(&mut self, op: &Token, right: &Expr) -> Result<LoxValue> {
     let right = self.eval_expr(right)?;
 
     match op.kind {
       TT::Bang => Ok(LoxValue::Boolean(!right.is_truthy())),
       TT::Minus => {
         if let LoxValue::Number(n) = right {
-          Ok(LoxValue::Number(-1.0 * n))
+          Ok(LoxValue::Number(-n))
         } else {
           Err(Error::Runtime(
             op.clone(),
@@ -206,9 +508,9 @@ impl Interpreter {
 
   fn eval_binary_expr(
     &mut self,
-    left: &Box<Expr>,
+    left: &Expr,
     op: &Token,
-    right: &Box<Expr>,
+    right: &Expr,
   ) -> Result<LoxValue> {
     use LoxValue as LV;
 
@@ -237,7 +539,7 @@ impl Interpreter {
       // plus is overloaded, to work on strings or numbers
       TT::Plus => match (left, right) {
         (LV::Number(a), LV::Number(b)) => LV::Number(a + b),
-        (LV::String(a), LV::String(b)) => LV::String(a + &b),
+        (LV::String(a), LV::String(b)) => LV::String([&*a, &*b].concat().into()),
         _ => {
           return Err(Error::Runtime(
             op.clone(),
@@ -280,3 +582,20 @@ fn assert_two_numbers(op: &Token, left: &LoxValue, right: &LoxValue) -> Result<(
     ))
   }
 }
+
+fn arity_mismatch(min: usize, max: Option<usize>, got: usize) -> String {
+  let plural = |n: usize| if n == 1 { "argument" } else { "arguments" };
+
+  match max {
+    Some(max) if max == min => format!("Expected {min} arguments but got {got}."),
+    Some(max) => format!("Expected {min} to {max} {} but got {got}.", plural(max)),
+    None => format!("Expected at least {min} {} but got {got}.", plural(min)),
+  }
+}
+
+// Where we are on the Rust stack, give or take, for measuring how much of it
+// we've used.
+fn stack_address() -> usize {
+  let marker = 0u8;
+  std::hint::black_box(&marker) as *const u8 as usize
+}
@@ -0,0 +1,216 @@
+use std::fs;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+use crate::gc::GcConfig;
+use crate::stmt::Stmt;
+use crate::symbol::Symbol;
+use crate::value::{FromLoxArgs, FromLoxValue, LoxValue, Param, ParamType, Signature};
+use crate::{Error, Interpreter, Parser, Resolver, Result, Scanner};
+
+// The bits of the interpreter meant for Rust programs that run Lox code:
+// seeding it with values, giving it functions to call back into, and reading
+// back whatever the script left behind.
+//
+//   let mut interpreter = Interpreter::new();
+//   interpreter.define_global("limit", LoxValue::Number(10.0));
+//   interpreter.register_fn("greet", |name: String| Ok(format!("hi, {name}")));
+//   interpreter.eval("var answer = greet(\"you\");")?;
+//   let answer = interpreter.get_global("answer");
+impl Interpreter {
+  // Runs some source code from start to finish, in this interpreter's global
+  // scope, so anything it defines sticks around for next time. If the code
+  // ends in an expression statement, we hand back its value; otherwise, nil.
+  // The semicolon after that last expression is optional, so "1 + 2" and
+  // "var x = 1; x" both work.
+  //
+  // Code that doesn't scan, parse or resolve comes back as Error::Invalid,
+  // with every error that was found; nothing in it runs.
+  pub fn eval(&mut self, source: &str) -> Result<LoxValue> {
+    let tokens = Scanner::new(source.to_string()).into_tokens();
+
+    let mut parser = Parser::new(tokens);
+    let mut statements =
+      parser.parse_script().map_err(|_| Error::Invalid(parser.errors))?;
+
+    let mut resolver = Resolver::new();
+    if resolver.resolve(&statements).is_err() {
+      return Err(Error::Invalid(resolver.errors));
+    }
+
+    let last = match statements.last() {
+      Some(Stmt::Expression(_)) => statements.pop(),
+      _ => None,
+    };
+
+    self.interpret(statements)?;
+
+    match last {
+      Some(Stmt::Expression(expr)) => self.evaluate(&expr),
+      _ => Ok(LoxValue::Nil),
+    }
+  }
+
+  // Like eval, but for the source in a file.
+  pub fn run_file(&mut self, path: &str) -> Result<LoxValue> {
+    let contents = fs::read(path)?;
+    let source = String::from_utf8(contents)
+      .map_err(|_| Error::Io(std::io::ErrorKind::InvalidData.into()))?;
+
+    self.eval(&source)
+  }
+
+  // Limits how much more work this interpreter will do, for running code
+  // that can't be trusted to finish. Each call, and each time around a loop,
+  // uses up a step; once they're gone, whatever's running stops with
+  // Error::OutOfSteps. The budget carries over from one run to the next, so
+  // set it again before each one to give them all the same. None (which is
+  // how it starts out) means no limit.
+  pub fn set_step_budget(&mut self, steps: Option<u64>) {
+    self.steps_left = steps;
+  }
+
+  pub fn steps_left(&self) -> Option<u64> {
+    self.steps_left
+  }
+
+  // How the cycle collector behaves; see gc.rs. It's the same sort of config
+  // as the VM takes.
+  pub fn set_gc_config(&mut self, config: GcConfig) {
+    self.gc = config;
+    self.next_gc = config.initial_threshold;
+  }
+
+  // Limits how deep calls can nest, so that runaway recursion stops with
+  // Error::StackOverflow rather than taking the whole process down with it.
+  // The default is 256.
+  pub fn set_max_call_depth(&mut self, depth: usize) {
+    self.max_call_depth = depth;
+  }
+
+  // Limits how much of the Rust stack calls can take up, which runs out first
+  // when each call needs a lot of it (as they do in debug builds, or when
+  // their bodies nest deeply). That's a stack overflow too. The default, a
+  // MiB, is safe on any thread with 2 MiB of stack; raise it to let scripts
+  // recurse further on threads with more.
+  pub fn set_max_stack(&mut self, bytes: usize) {
+    self.max_stack = bytes;
+  }
+
+  // Gives the interpreter a flag to keep an eye on: when anybody (another
+  // thread, say) sets it, whatever's running stops with Error::Interrupted
+  // at the next step. It's up to them to clear it again afterwards.
+  pub fn set_interrupt_flag(&mut self, flag: Arc<AtomicBool>) {
+    self.interrupt = Some(flag);
+  }
+
+  // Defines (or redefines) a global variable, just as a top-level `var`
+  // would.
+  pub fn define_global(&mut self, name: &str, value: impl Into<LoxValue>) {
+    self
+      .globals
+      .borrow_mut()
+      .define(Symbol::intern(name), value.into());
+  }
+
+  // The value of a global variable, if there is one by that name.
+  pub fn get_global(&self, name: &str) -> Option<LoxValue> {
+    let name = Symbol::lookup(name)?;
+    self.globals.borrow().get_here(name)
+  }
+
+  // Makes a Rust closure callable from Lox as a global function. Its
+  // parameters can be any NativeArg type, and it can return anything that
+  // goes into a LoxValue; calls with the wrong number or sort of arguments
+  // are turned away with a runtime error before the closure ever sees them.
+  // For instance:
+  //
+  //   interpreter.register_fn("add", |a: f64, b: f64| Ok(a + b));
+  //
+  // For anything fancier (optional or variadic parameters, say), build the
+  // value with LoxValue::new_native and hand it to define_global instead.
+  pub fn register_fn<Args, F: NativeFn<Args>>(&mut self, name: &str, func: F) {
+    let call = move |_interp: &mut Interpreter, args: Vec<LoxValue>| func.call(args);
+    let native = LoxValue::new_native(name.into(), F::signature(), Box::new(call));
+
+    self.define_global(name, native);
+  }
+}
+
+// Rust types that registered functions can take as arguments, along with the
+// sort of parameter each one makes. Options are optional parameters: they're
+// None when the argument is nil, or left off altogether.
+pub trait NativeArg: FromLoxValue {
+  const PARAM: Param;
+}
+
+impl NativeArg for f64 {
+  const PARAM: Param = Param::Required(ParamType::Number);
+}
+
+impl NativeArg for String {
+  const PARAM: Param = Param::Required(ParamType::String);
+}
+
+impl NativeArg for bool {
+  const PARAM: Param = Param::Required(ParamType::Boolean);
+}
+
+impl NativeArg for LoxValue {
+  const PARAM: Param = Param::Required(ParamType::Any);
+}
+
+impl<T: NativeArg> NativeArg for Option<T> {
+  const PARAM: Param = match T::PARAM {
+    Param::Required(ty) | Param::Optional(ty) | Param::Variadic(ty) => {
+      Param::Optional(ty)
+    },
+  };
+}
+
+macro_rules! impl_native_arg {
+  ($($int:ty),*) => {
+    $(
+      impl NativeArg for $int {
+        const PARAM: Param = Param::Required(ParamType::Number);
+      }
+    )*
+  };
+}
+
+impl_native_arg!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+// Closures that register_fn knows how to call. Args is the closure's
+// parameter types, as a tuple; it's only there to tell the impls below apart.
+pub trait NativeFn<Args>: 'static {
+  fn signature() -> Signature;
+  fn call(&self, args: Vec<LoxValue>) -> Result<LoxValue>;
+}
+
+macro_rules! impl_native_fn {
+  ($($arg:ident $name:ident),*) => {
+    impl<Func, Ret, $($arg),*> NativeFn<($($arg,)*)> for Func
+    where
+      Func: Fn($($arg),*) -> Result<Ret> + 'static,
+      Ret: Into<LoxValue>,
+      $($arg: NativeArg,)*
+    {
+      fn signature() -> Signature {
+        Signature::new(vec![$($arg::PARAM),*])
+      }
+
+      fn call(&self, args: Vec<LoxValue>) -> Result<LoxValue> {
+        let ($($name,)*) = <($($arg,)*)>::from_lox_args(args)?;
+        self($($name),*).map(Into::into)
+      }
+    }
+  };
+}
+
+impl_native_fn!();
+impl_native_fn!(A a);
+impl_native_fn!(A a, B b);
+impl_native_fn!(A a, B b, C c);
+impl_native_fn!(A a, B b, C c, D d);
+impl_native_fn!(A a, B b, C c, D d, E e);
+impl_native_fn!(A a, B b, C c, D d, E e, G g);
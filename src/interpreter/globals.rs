@@ -1,10 +1,10 @@
 use crate::environment::Environment;
 use crate::interpreter::Interpreter;
-use crate::value::LoxValue;
+use crate::value::{LoxValue, Signature};
 use crate::Result;
 
 pub fn install_in(env: &mut Environment) {
-  env.define("clock", clock());
+  env.define("clock".into(), clock());
 }
 
 fn clock() -> LoxValue {
@@ -19,5 +19,5 @@ fn clock() -> LoxValue {
     Ok(secs)
   };
 
-  LoxValue::new_callable("clock".into(), 0, Box::new(func))
+  LoxValue::new_native("clock".into(), Signature::new(vec![]), Box::new(func))
 }
@@ -0,0 +1,73 @@
+use std::io::{self, Write};
+
+// Where `print` (and any native that wants to say something) writes to. It's
+// stdout unless whoever made the interpreter said otherwise, which is handy
+// for tests, or for running scripts somewhere that there's no terminal.
+pub struct Output(Box<dyn Write>);
+
+// For handing output to a closure rather than a writer. `print` reaches us as
+// a handful of write_fmt fragments, so we hold on to them and call the closure
+// once per whole line, newline included. Whatever's left without a newline goes
+// out on flush, or when the writer's dropped.
+struct FnWriter<F: FnMut(&str)> {
+  func: F,
+  line: Vec<u8>,
+}
+
+impl Output {
+  pub fn new(writer: impl Write + 'static) -> Self {
+    Output(Box::new(writer))
+  }
+
+  pub fn from_fn(func: impl FnMut(&str) + 'static) -> Self {
+    Output::new(FnWriter {
+      func,
+      line: vec![],
+    })
+  }
+}
+
+impl Write for Output {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    self.0.write(buf)
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    self.0.flush()
+  }
+}
+
+impl<F: FnMut(&str)> Write for FnWriter<F> {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    self.line.extend_from_slice(buf);
+
+    while let Some(i) = self.line.iter().position(|&b| b == b'\n') {
+      let rest = self.line.split_off(i + 1);
+      (self.func)(&String::from_utf8_lossy(&self.line));
+      self.line = rest;
+    }
+
+    Ok(buf.len())
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    if !self.line.is_empty() {
+      (self.func)(&String::from_utf8_lossy(&self.line));
+      self.line.clear();
+    }
+
+    Ok(())
+  }
+}
+
+impl<F: FnMut(&str)> Drop for FnWriter<F> {
+  fn drop(&mut self) {
+    let _ = self.flush();
+  }
+}
+
+impl std::fmt::Debug for Output {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "<output>")
+  }
+}
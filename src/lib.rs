@@ -1,16 +1,33 @@
+pub mod chunk;
+pub mod compiler;
+pub mod diagnostics;
+pub mod disassembler;
 mod environment;
 pub mod errors;
 pub mod expr;
+pub mod gc;
 pub mod interpreter;
 pub mod parser;
+pub mod resolver;
 pub mod scanner;
+pub mod serialize;
 pub mod stmt;
+pub mod symbol;
 pub mod token;
 pub mod tools;
-mod value;
+pub mod value;
+pub mod vm;
 
+pub use compiler::Compiler;
 pub use errors::{Error, Result};
 pub use interpreter::Interpreter;
 pub use parser::Parser;
+pub use resolver::Resolver;
 pub use scanner::Scanner;
 pub use token::{Token, TokenType};
+pub use vm::Vm;
+
+// How deep calls can nest before it's a stack overflow, on either backend
+// (the tree-walker can be told otherwise), so that the same script recurses
+// just as far on both.
+pub(crate) const MAX_CALL_DEPTH: usize = 256;
@@ -1,6 +1,7 @@
 use std::cell::RefCell;
+use std::rc::Rc;
 
-use crate::expr::{self, Expr, Literal};
+use crate::expr::{self, Depth, Expr, Literal};
 use crate::stmt::Stmt;
 use crate::{Error, Result, Token, TokenType as TT};
 
@@ -9,14 +10,28 @@ pub struct Parser {
   tokens: Vec<Token>,
   current: RefCell<usize>,
   pub errors: Vec<Error>,
+  hit_end: bool,
+  open_tail: bool,
 }
 
 impl Parser {
+  // Anything the scanner choked on is already an error token, so those get
+  // reported first, along with whatever we find wrong ourselves.
   pub fn new(tokens: Vec<Token>) -> Self {
+    let errors = tokens
+      .iter()
+      .filter_map(|tok| match &tok.kind {
+        TT::Error(msg) => Some(Error::Scan(tok.span, msg.clone())),
+        _ => None,
+      })
+      .collect();
+
     Parser {
       tokens,
       current: RefCell::new(0),
-      errors: vec![],
+      errors,
+      hit_end: false,
+      open_tail: false,
     }
   }
 
@@ -24,6 +39,12 @@ impl Parser {
     !self.errors.is_empty()
   }
 
+  // True if any error happened because we got to the end of the tokens while
+  // still in the middle of something, like an unclosed block.
+  pub fn ran_out_of_input(&self) -> bool {
+    self.hit_end
+  }
+
   pub fn parse(&mut self) -> Result<Vec<Stmt>> {
     let mut statements = vec![];
 
@@ -32,7 +53,8 @@ impl Parser {
       if let Ok(stmt) = stmt {
         statements.push(stmt);
       } else {
-        self.errors.push(stmt.unwrap_err());
+        self.record(stmt.unwrap_err());
+        self.hit_end |= self.is_at_end();
         self.synchronize();
       }
     }
@@ -40,15 +62,62 @@ impl Parser {
     if self.errors.is_empty() {
       Ok(statements)
     } else {
+      // the scanner's errors went in first, so put them back in order
+      self.errors.sort_by_key(|err| err.span().map(|span| span.start));
       Err(Error::ParseFailed)
     }
   }
 
+  // Like parse, but the last statement can be an expression without its
+  // semicolon, so "print 1; x" is fine. That's how eval takes its code.
+  pub fn parse_script(&mut self) -> Result<Vec<Stmt>> {
+    self.open_tail = true;
+    self.parse()
+  }
+
+  // Parses the input as one lone expression, with or without a semicolon on
+  // the end. The REPL uses this to pick out things it should echo.
+  pub fn parse_expression(&mut self) -> Result<Box<Expr>> {
+    let result = self.expression().and_then(|expr| {
+      self.next_matches(&[TT::Semicolon]);
+
+      match self.peek() {
+        Some(tok) if !self.is_at_end() => {
+          Err(Error::Parse(tok.clone(), "Expect end of expression.".into()))
+        },
+        _ => Ok(expr),
+      }
+    });
+
+    match result {
+      Ok(expr) if self.errors.is_empty() => Ok(expr),
+      Ok(_) => Err(Error::ParseFailed),
+      Err(err) => {
+        self.record(err);
+        Err(Error::ParseFailed)
+      },
+    }
+  }
+
+  // Tripping over an error token is just the scanner's error again, which
+  // we've already got, so that doesn't get recorded twice.
+  fn record(&mut self, err: Error) {
+    let repeat = match &err {
+      Error::Parse(tok, _) => tok.is_error() || self.peek().is_some_and(Token::is_error),
+      _ => false,
+    };
+
+    if !repeat {
+      self.errors.push(err);
+    }
+  }
+
   fn declaration(&self) -> Result<Stmt> {
     let next = self.peek().expect("no token to parse in declaration()");
     self.advance();
 
     match next.kind {
+      TT::Class => self.class_declaration(),
       TT::Fun => self.function("function"),
       TT::Var => self.var_declaration(),
       _ => {
@@ -58,6 +127,29 @@ impl Parser {
     }
   }
 
+  fn class_declaration(&self) -> Result<Stmt> {
+    let name = self.consume_identifier("Expect class name.")?;
+
+    let superclass = if self.next_matches(&[TT::Less]) {
+      let super_name = self.consume_identifier("Expect superclass name.")?;
+      Some(Box::new(Expr::Variable(Depth::default(), super_name)))
+    } else {
+      None
+    };
+
+    self.consume(TT::LeftBrace, "Expect '{' before class body.")?;
+
+    let mut methods = vec![];
+
+    while !self.check(&TT::RightBrace) && !self.is_at_end() {
+      methods.push(self.function("method")?);
+    }
+
+    self.consume(TT::RightBrace, "Expect '}' after class body.")?;
+
+    Ok(Stmt::Class(name, superclass, methods))
+  }
+
   fn function(&self, kind: &str) -> Result<Stmt> {
     let name = self.consume_identifier(&format!("expect {kind} name"))?;
 
@@ -90,7 +182,7 @@ impl Parser {
     };
 
     self.consume(TT::Semicolon, "Expect ';' after variable declaration.")?;
-    Ok(Stmt::Var(name.lexeme(), initializer))
+    Ok(Stmt::Var(name, initializer))
   }
 
   fn statement(&self) -> Result<Stmt> {
@@ -122,9 +214,9 @@ impl Parser {
   fn return_statement(&self) -> Result<Stmt> {
     let keyword = self.previous().unwrap();
     let value = if self.check(&TT::Semicolon) {
-      expr::nil_expression()
+      None
     } else {
-      self.expression()?
+      Some(self.expression()?)
     };
 
     self.consume(TT::Semicolon, "Expect ';' after return value.")?;
@@ -148,17 +240,19 @@ impl Parser {
   }
 
   fn while_statement(&self) -> Result<Stmt> {
+    let keyword = self.previous().unwrap().clone();
     self.consume(TT::LeftParen, "Expect '(' after 'while'.")?;
     let cond = self.expression()?;
     self.consume(TT::RightParen, "Expect ')' after while condition.")?;
 
     let body = self.statement()?;
-    Ok(Stmt::While(cond, Box::new(body)))
+    Ok(Stmt::While(keyword, cond, Box::new(body)))
   }
 
   // a for statement is just sugar for a while, so this desugars it all
   fn for_statement(&self) -> Result<Stmt> {
     // first parse
+    let keyword = self.previous().unwrap().clone();
     self.consume(TT::LeftParen, "Expect '(' after 'for'.")?;
 
     let initializer: Option<Stmt> = if self.next_matches(&[TT::Semicolon]) {
@@ -195,7 +289,7 @@ impl Parser {
 
     // and the condition onto the front of it
     let condition = cond.unwrap_or_else(|| expr::bool_expression(true));
-    body = Stmt::While(condition, Box::new(body));
+    body = Stmt::While(keyword, condition, Box::new(body));
 
     // and the initializer before the whole thing
     if let Some(init) = initializer {
@@ -218,7 +312,11 @@ impl Parser {
 
   fn expression_statement(&self) -> Result<Stmt> {
     let value = self.expression()?;
-    self.consume(TT::Semicolon, "Expect ';' after value.")?;
+
+    if !(self.open_tail && self.is_at_end()) {
+      self.consume(TT::Semicolon, "Expect ';' after value.")?;
+    }
+
     Ok(Stmt::Expression(value))
   }
 
@@ -233,13 +331,15 @@ impl Parser {
       let equals = self.previous().unwrap();
       let value = self.assignment()?;
 
-      if let Expr::Variable(tok) = *expr {
-        Ok(Box::new(Expr::Assign(tok, value)))
-      } else {
-        Err(Error::Parse(
+      match *expr {
+        Expr::Variable(_, tok) => {
+          Ok(Box::new(Expr::Assign(Depth::default(), tok, value)))
+        },
+        Expr::Get(object, name) => Ok(Box::new(Expr::Set(object, name, value))),
+        _ => Err(Error::Parse(
           equals.clone(),
           format!("invalid assignment target: {}", equals),
-        ))
+        )),
       }
     } else {
       Ok(expr)
@@ -338,6 +438,9 @@ impl Parser {
     loop {
       if self.next_matches(&[TT::LeftParen]) {
         expr = self.finish_call(expr)?;
+      } else if self.next_matches(&[TT::Dot]) {
+        let name = self.consume_identifier("Expect property name after '.'.")?;
+        expr = Box::new(Expr::Get(expr, name));
       } else {
         break;
       }
@@ -375,7 +478,7 @@ impl Parser {
       TT::False => Expr::Literal(Literal::Boolean(false)),
       TT::Nil => Expr::Literal(Literal::Nil),
       TT::Number(n) => Expr::Literal(Literal::Number(n)),
-      TT::String(ref s) => Expr::Literal(Literal::String(s.clone())),
+      TT::String(ref s) => Expr::Literal(Literal::String(Rc::clone(s))),
       TT::LeftParen => {
         self.advance();
         let expr = self.expression()?;
@@ -383,7 +486,15 @@ impl Parser {
         self.rewind(); // silly
         Expr::Grouping(expr)
       },
-      TT::Identifier(_) => Expr::Variable(next.clone()),
+      TT::Super => {
+        self.advance();
+        self.consume(TT::Dot, "Expect '.' after 'super'.")?;
+        let method = self.consume_identifier("Expect superclass method name.")?;
+        self.rewind(); // silly
+        Expr::Super(Depth::default(), next.clone(), method)
+      },
+      TT::This => Expr::This(Depth::default(), next.clone()),
+      TT::Identifier(_) => Expr::Variable(Depth::default(), next.clone()),
       _ => {
         return Err(Error::Parse(
           next.clone(),
@@ -446,19 +557,13 @@ impl Parser {
       self.advance();
       Ok(self.previous().unwrap().clone())
     } else {
-      Err(Error::Parse(
-        self.previous().unwrap().clone(),
-        format!("{err}"),
-      ))
+      Err(Error::Parse(self.previous().unwrap().clone(), err.to_string()))
     }
   }
 
   fn consume_identifier(&self, err: &str) -> Result<Token> {
     if self.is_at_end() || !self.peek().unwrap().is_identifier() {
-      Err(Error::Parse(
-        self.previous().unwrap().clone(),
-        format!("{err}"),
-      ))
+      Err(Error::Parse(self.previous().unwrap().clone(), err.to_string()))
     } else {
       self.advance();
       Ok(self.previous().unwrap().clone())
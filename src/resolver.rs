@@ -0,0 +1,281 @@
+use std::collections::HashMap;
+
+use crate::expr::{Depth, Expr};
+use crate::stmt::Stmt;
+use crate::symbol::Symbol;
+use crate::{Error, Result, Token};
+
+// Each scope maps a name to whether or not we've finished defining it yet.
+type Scope = HashMap<Symbol, bool>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum FunctionType {
+  None,
+  Function,
+  Initializer,
+  Method,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ClassType {
+  None,
+  Class,
+  Subclass,
+}
+
+// The resolver walks the whole tree once before we run anything, and works
+// out how many environments up the chain each local variable lives, noting it
+// down in the tree itself. Anything it can't find in a local scope is assumed
+// to be a global.
+pub struct Resolver {
+  scopes: Vec<Scope>,
+  current_function: FunctionType,
+  current_class: ClassType,
+  pub errors: Vec<Error>,
+}
+
+impl Default for Resolver {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Resolver {
+  pub fn new() -> Self {
+    Resolver {
+      scopes: vec![],
+      current_function: FunctionType::None,
+      current_class: ClassType::None,
+      errors: vec![],
+    }
+  }
+
+  pub fn has_errors(&self) -> bool {
+    !self.errors.is_empty()
+  }
+
+  pub fn resolve(&mut self, statements: &[Stmt]) -> Result<()> {
+    self.resolve_statements(statements);
+
+    if self.errors.is_empty() {
+      Ok(())
+    } else {
+      Err(Error::ResolveFailed)
+    }
+  }
+
+  fn resolve_statements(&mut self, statements: &[Stmt]) {
+    for stmt in statements {
+      self.resolve_stmt(stmt);
+    }
+  }
+
+  fn resolve_stmt(&mut self, stmt: &Stmt) {
+    match stmt {
+      Stmt::Empty => (),
+      Stmt::Block(block) => {
+        self.begin_scope();
+        self.resolve_statements(block);
+        self.end_scope();
+      },
+      Stmt::Expression(e) | Stmt::Print(e) => self.resolve_expr(e),
+      Stmt::Var(name, init) => {
+        self.declare(name);
+        self.resolve_expr(init);
+        self.define(name);
+      },
+      Stmt::Function(name, params, body) => {
+        // define eagerly, so that the function can refer to itself
+        self.declare(name);
+        self.define(name);
+        self.resolve_function(params, body, FunctionType::Function);
+      },
+      Stmt::Class(name, superclass, methods) => {
+        let enclosing = self.current_class;
+        self.current_class = ClassType::Class;
+
+        self.declare(name);
+        self.define(name);
+
+        if let Some(superclass) = superclass {
+          if let Expr::Variable(_, super_name) = superclass.as_ref() {
+            if super_name.symbol() == name.symbol() {
+              self.error(super_name, "A class can't inherit from itself.");
+            }
+          }
+
+          self.current_class = ClassType::Subclass;
+          self.resolve_expr(superclass);
+
+          self.begin_scope();
+          self.scopes.last_mut().unwrap().insert(Symbol::SUPER, true);
+        }
+
+        self.begin_scope();
+        self.scopes.last_mut().unwrap().insert(Symbol::THIS, true);
+
+        for method in methods {
+          let Stmt::Function(mname, params, body) = method else {
+            unreachable!("non-function method in class {}", name.lexeme());
+          };
+
+          let kind = if mname.symbol() == Symbol::INIT {
+            FunctionType::Initializer
+          } else {
+            FunctionType::Method
+          };
+
+          self.resolve_function(params, body, kind);
+        }
+
+        self.end_scope();
+
+        if superclass.is_some() {
+          self.end_scope();
+        }
+
+        self.current_class = enclosing;
+      },
+      Stmt::If(cond, then_branch, else_branch) => {
+        self.resolve_expr(cond);
+        self.resolve_stmt(then_branch);
+        self.resolve_stmt(else_branch);
+      },
+      Stmt::While(_, cond, body) => {
+        self.resolve_expr(cond);
+        self.resolve_stmt(body);
+      },
+      Stmt::Return(keyword, value) => {
+        if self.current_function == FunctionType::None {
+          self.error(keyword, "Can't return from top-level code.");
+        }
+
+        if let Some(value) = value {
+          if self.current_function == FunctionType::Initializer {
+            self.error(keyword, "Can't return a value from an initializer.");
+          }
+
+          self.resolve_expr(value);
+        }
+      },
+    }
+  }
+
+  fn resolve_expr(&mut self, expr: &Expr) {
+    match expr {
+      Expr::Literal(_) => (),
+      Expr::Grouping(e) | Expr::Unary(_, e) => self.resolve_expr(e),
+      Expr::Binary(left, _, right) | Expr::Logical(left, _, right) => {
+        self.resolve_expr(left);
+        self.resolve_expr(right);
+      },
+      Expr::Call(callee, _, args) => {
+        self.resolve_expr(callee);
+
+        for arg in args {
+          self.resolve_expr(arg);
+        }
+      },
+      Expr::Variable(depth, name) => {
+        let sym = name.symbol();
+        let in_initializer = self
+          .scopes
+          .last()
+          .is_some_and(|scope| scope.get(&sym) == Some(&false));
+
+        if in_initializer {
+          self.error(name, "Can't read local variable in its own initializer.");
+        }
+
+        self.resolve_local(depth, name);
+      },
+      Expr::Assign(depth, name, value) => {
+        self.resolve_expr(value);
+        self.resolve_local(depth, name);
+      },
+      Expr::Get(object, _) => self.resolve_expr(object),
+      Expr::Set(object, _, value) => {
+        self.resolve_expr(value);
+        self.resolve_expr(object);
+      },
+      Expr::Super(depth, keyword, _) => {
+        match self.current_class {
+          ClassType::None => self.error(keyword, "Can't use 'super' outside of a class."),
+          ClassType::Class => {
+            self.error(keyword, "Can't use 'super' in a class with no superclass.")
+          },
+          ClassType::Subclass => (),
+        }
+
+        self.resolve_local(depth, keyword);
+      },
+      Expr::This(depth, keyword) => {
+        if self.current_class == ClassType::None {
+          self.error(keyword, "Can't use 'this' outside of a class.");
+          return;
+        }
+
+        self.resolve_local(depth, keyword);
+      },
+    }
+  }
+
+  fn resolve_function(&mut self, params: &[Token], body: &[Stmt], kind: FunctionType) {
+    let enclosing = self.current_function;
+    self.current_function = kind;
+
+    self.begin_scope();
+
+    for param in params {
+      self.declare(param);
+      self.define(param);
+    }
+
+    self.resolve_statements(body);
+    self.end_scope();
+
+    self.current_function = enclosing;
+  }
+
+  fn resolve_local(&mut self, slot: &Depth, name: &Token) {
+    let sym = name.symbol();
+
+    for (depth, scope) in self.scopes.iter().rev().enumerate() {
+      if scope.contains_key(&sym) {
+        slot.set(depth);
+        return;
+      }
+    }
+
+    // not found, so we'll assume it's global
+  }
+
+  // helpers
+  fn begin_scope(&mut self) {
+    self.scopes.push(HashMap::new());
+  }
+
+  fn end_scope(&mut self) {
+    self.scopes.pop();
+  }
+
+  fn declare(&mut self, name: &Token) {
+    let Some(scope) = self.scopes.last_mut() else {
+      return;
+    };
+
+    if scope.insert(name.symbol(), false).is_some() {
+      self.error(name, "Already a variable with this name in this scope.");
+    }
+  }
+
+  fn define(&mut self, name: &Token) {
+    if let Some(scope) = self.scopes.last_mut() {
+      scope.insert(name.symbol(), true);
+    }
+  }
+
+  fn error(&mut self, token: &Token, msg: &str) {
+    self.errors.push(Error::Resolve(token.clone(), msg.to_string()));
+  }
+}
@@ -1,50 +1,94 @@
+use crate::token::Span;
 use crate::{Error, Result, Token, TokenType};
 
 #[derive(Debug)]
 pub struct Scanner {
   source: Vec<char>,
+  offsets: Vec<usize>, // the byte offset of each char, and then the end
   tokens: Vec<Token>,
   current: usize,
   start: usize,
   line: usize,
+  line_start: usize, // index of the first char on this line
+  start_line: usize, // where the token we're on started
+  start_column: usize,
+  in_string: bool, // we ran out of source partway through a string
+  pub errors: Vec<Error>,
 }
 
 impl Scanner {
   pub fn new(source: String) -> Self {
+    let offsets = source
+      .char_indices()
+      .map(|(offset, _)| offset)
+      .chain([source.len()])
+      .collect();
+
     Scanner {
       source: source.chars().collect(),
+      offsets,
       tokens: vec![],
       current: 0,
       start: 0,
       line: 1,
+      line_start: 0,
+      start_line: 1,
+      start_column: 1,
+      in_string: false,
+      errors: vec![],
     }
   }
 
-  // really this should return an iterator or something, but hey
+  pub fn has_errors(&self) -> bool {
+    !self.errors.is_empty()
+  }
+
+  // Really this should return an iterator or something, but hey. Errors
+  // don't stop the scan: each one is recorded, and leaves an error token in
+  // the stream for the parser to trip over (and report) in its turn.
   pub fn scan_tokens(&mut self) -> Result<&Vec<Token>> {
     while !self.is_at_end() {
-      self.start = self.current;
-      self.scan_token()?;
+      self.start_token();
+      self.scan_token();
     }
 
-    self.tokens.push(Token::new(TokenType::EOF, self.line));
+    self.start_token();
+    self.add_token(TokenType::EOF);
 
-    Ok(&self.tokens)
+    if self.errors.is_empty() {
+      Ok(&self.tokens)
+    } else {
+      Err(Error::ScanFailed)
+    }
   }
 
-  pub fn into_tokens(mut self) -> Result<Vec<Token>> {
-    if self.tokens.len() == 0 {
-      self.scan_tokens()?;
+  // All the tokens, error tokens included. It's up to whoever's using them
+  // to notice those (the parser does).
+  pub fn into_tokens(mut self) -> Vec<Token> {
+    if self.tokens.is_empty() {
+      let _ = self.scan_tokens();
     }
 
-    Ok(self.tokens)
+    self.tokens
+  }
+
+  fn start_token(&mut self) {
+    self.start = self.current;
+    self.start_line = self.line;
+    self.start_column = self.current - self.line_start + 1;
+  }
+
+  // True if scanning failed because the source stopped in the middle of a
+  // string, which (in the REPL, say) means there's probably more coming.
+  pub fn ended_in_string(&self) -> bool {
+    self.in_string
   }
 
   fn is_at_end(&self) -> bool {
     self.current >= self.source.len()
   }
 
-  fn scan_token(&mut self) -> Result<()> {
+  fn scan_token(&mut self) {
     let c = self.advance();
 
     use TokenType as TT;
@@ -109,21 +153,17 @@ impl Scanner {
 
       // whitespace
       ' ' | '\r' | '\t' => (),
-      '\n' => {
-        self.line += 1;
-      },
+      '\n' => self.newline(),
 
       // string and numeric literals
-      '"' => self.read_string()?,
+      '"' => self.read_string(),
       c if c.is_ascii_digit() => self.read_number(),
 
       // identifiers and keywords
       c if c.is_ascii_alphabetic() || c == '_' => self.read_identifier(),
 
-      _ => return Err(Error::Scan(self.line, format!("unexpected character {c}"))),
+      _ => self.error(format!("unexpected character {c}")),
     };
-
-    Ok(())
   }
 
   fn advance(&mut self) -> char {
@@ -132,6 +172,10 @@ impl Scanner {
     c
   }
 
+  fn previous(&self) -> char {
+    self.source[self.current - 1]
+  }
+
   fn peek(&mut self) -> char {
     if self.is_at_end() {
       '\0'
@@ -141,7 +185,7 @@ impl Scanner {
   }
 
   fn peek_next(&mut self) -> char {
-    if self.current + 1 > self.source.len() {
+    if self.current + 1 >= self.source.len() {
       '\0'
     } else {
       self.source[self.current + 1]
@@ -162,20 +206,42 @@ impl Scanner {
   }
 
   fn add_token(&mut self, kind: TokenType) {
-    self.tokens.push(Token::new(kind, self.line));
+    self.tokens.push(Token::new(kind, self.span()));
   }
 
-  fn read_string(&mut self) -> Result<()> {
-    while self.peek() != '"' && !self.is_at_end() {
-      if self.peek() == '\n' {
-        self.line += 1;
-      }
+  fn error(&mut self, msg: String) {
+    self.errors.push(Error::Scan(self.span(), msg.clone()));
+    self.add_token(TokenType::Error(msg));
+  }
+
+  // covers whatever we've looked at since the current token started
+  fn span(&self) -> Span {
+    Span::new(
+      self.offsets[self.start],
+      self.offsets[self.current],
+      self.start_line,
+      self.start_column,
+    )
+  }
+
+  // call this just after consuming a newline
+  fn newline(&mut self) {
+    self.line += 1;
+    self.line_start = self.current;
+  }
 
+  fn read_string(&mut self) {
+    while self.peek() != '"' && !self.is_at_end() {
       self.advance();
+
+      if self.previous() == '\n' {
+        self.newline();
+      }
     }
 
     if self.is_at_end() {
-      return Err(Error::Scan(self.line, "unterminated string".into()));
+      self.in_string = true;
+      return self.error("unterminated string".into());
     }
 
     self.advance(); // closing quote
@@ -183,8 +249,7 @@ impl Scanner {
       .iter()
       .collect();
 
-    self.add_token(TokenType::String(val));
-    Ok(())
+    self.add_token(TokenType::String(val.into()));
   }
 
   fn read_number(&mut self) {
@@ -212,6 +277,6 @@ impl Scanner {
       self.advance();
     }
 
-    self.add_token(TokenType::new_identifier(self.current_string()));
+    self.add_token(TokenType::new_identifier(&self.current_string()));
   }
 }
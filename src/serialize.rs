@@ -0,0 +1,466 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::chunk::{Chunk, Constant, Function, OpCode};
+use crate::{Error, Result};
+
+// The on-disk format for compiled programs. Everything is little-endian, and
+// lengths are u32s:
+//
+//   file     := MAGIC version:u16 checksum:u64 function
+//   function := name:string arity:u8 upvalue_count:u16 chunk
+//   chunk    := code:bytes lines constants
+//   lines    := count:u32 (line:u32 run:u32)*
+//   constants := count:u32 constant*
+//   constant := 0 f64 | 1 string | 2 function
+//   string, bytes := len:u32 byte*
+//
+// The checksum is FNV-1a over everything after it. It's there to catch files
+// that got mangled somehow, which the structural checks below can't always
+// tell apart from real code. Bump FORMAT_VERSION whenever any of this, or the
+// opcodes, change.
+pub const MAGIC: &[u8; 4] = b"LOXC";
+pub const FORMAT_VERSION: u16 = 1;
+
+// deeper than this and we assume somebody's trying to blow our stack
+const MAX_NESTING: usize = 256;
+
+const TAG_NUMBER: u8 = 0;
+const TAG_STRING: u8 = 1;
+const TAG_FUNCTION: u8 = 2;
+
+pub fn is_compiled(bytes: &[u8]) -> bool {
+  bytes.starts_with(MAGIC)
+}
+
+pub fn write_program(function: &Function) -> Vec<u8> {
+  let mut body = vec![];
+  write_function(&mut body, function);
+
+  let mut out = Vec::from(*MAGIC);
+  out.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+  out.extend_from_slice(&checksum(&body).to_le_bytes());
+  out.extend_from_slice(&body);
+  out
+}
+
+pub fn read_program(bytes: &[u8]) -> Result<Rc<Function>> {
+  let mut reader = Reader { bytes, pos: 0 };
+
+  if reader.take(MAGIC.len())? != MAGIC {
+    return Err(invalid("not a compiled lox program"));
+  }
+
+  let version = reader.u16()?;
+  if version != FORMAT_VERSION {
+    return Err(invalid(format!(
+      "unsupported format version {version} (expected {FORMAT_VERSION})"
+    )));
+  }
+
+  let expected = reader.u64()?;
+  if checksum(&bytes[reader.pos..]) != expected {
+    return Err(invalid("checksum mismatch; the file is corrupt"));
+  }
+
+  let function = reader.function(0)?;
+
+  // the VM runs the script as a closure with nothing to capture and no
+  // arguments, so it can't be expecting either
+  if function.upvalue_count != 0 || function.arity != 0 {
+    return Err(invalid("top-level function takes arguments or upvalues"));
+  }
+
+  if reader.pos != bytes.len() {
+    return Err(invalid("trailing bytes after program"));
+  }
+
+  Ok(function)
+}
+
+fn write_function(out: &mut Vec<u8>, function: &Function) {
+  write_bytes(out, function.name.as_bytes());
+  out.push(function.arity as u8);
+  out.extend_from_slice(&(function.upvalue_count as u16).to_le_bytes());
+
+  let chunk = &function.chunk;
+  write_bytes(out, &chunk.code);
+
+  write_u32(out, chunk.lines().len());
+  for &(line, run) in chunk.lines() {
+    write_u32(out, line);
+    write_u32(out, run);
+  }
+
+  write_u32(out, chunk.constants.len());
+  for constant in &chunk.constants {
+    match constant {
+      Constant::Number(n) => {
+        out.push(TAG_NUMBER);
+        out.extend_from_slice(&n.to_le_bytes());
+      },
+      Constant::String(s) => {
+        out.push(TAG_STRING);
+        write_bytes(out, s.as_bytes());
+      },
+      Constant::Function(f) => {
+        out.push(TAG_FUNCTION);
+        write_function(out, f);
+      },
+    }
+  }
+}
+
+fn write_u32(out: &mut Vec<u8>, n: usize) {
+  out.extend_from_slice(&(n as u32).to_le_bytes());
+}
+
+fn write_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+  write_u32(out, bytes.len());
+  out.extend_from_slice(bytes);
+}
+
+fn checksum(bytes: &[u8]) -> u64 {
+  bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+    (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+  })
+}
+
+fn invalid(msg: impl Into<String>) -> Error {
+  Error::Bytecode(msg.into())
+}
+
+struct Reader<'a> {
+  bytes: &'a [u8],
+  pos: usize,
+}
+
+impl Reader<'_> {
+  fn take(&mut self, len: usize) -> Result<&[u8]> {
+    let end = self
+      .pos
+      .checked_add(len)
+      .filter(|&end| end <= self.bytes.len())
+      .ok_or_else(|| invalid("unexpected end of file"))?;
+
+    let slice = &self.bytes[self.pos..end];
+    self.pos = end;
+    Ok(slice)
+  }
+
+  fn u8(&mut self) -> Result<u8> {
+    Ok(self.take(1)?[0])
+  }
+
+  fn u16(&mut self) -> Result<u16> {
+    let bytes = self.take(2)?;
+    Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+  }
+
+  fn u32(&mut self) -> Result<usize> {
+    let bytes = self.take(4)?;
+    Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+  }
+
+  fn u64(&mut self) -> Result<u64> {
+    let mut buf = [0; 8];
+    buf.copy_from_slice(self.take(8)?);
+    Ok(u64::from_le_bytes(buf))
+  }
+
+  fn f64(&mut self) -> Result<f64> {
+    let mut buf = [0; 8];
+    buf.copy_from_slice(self.take(8)?);
+    Ok(f64::from_le_bytes(buf))
+  }
+
+  fn bytes(&mut self) -> Result<Vec<u8>> {
+    let len = self.u32()?;
+    Ok(self.take(len)?.to_vec())
+  }
+
+  fn string(&mut self) -> Result<String> {
+    String::from_utf8(self.bytes()?).map_err(|_| invalid("string is not valid UTF-8"))
+  }
+
+  fn function(&mut self, depth: usize) -> Result<Rc<Function>> {
+    if depth > MAX_NESTING {
+      return Err(invalid("functions nested too deeply"));
+    }
+
+    let name = self.string()?;
+    let arity = self.u8()? as usize;
+    let upvalue_count = self.u16()? as usize;
+
+    let code = self.bytes()?;
+
+    let line_count = self.u32()?;
+    let mut lines = Vec::new();
+    for _ in 0..line_count {
+      lines.push((self.u32()?, self.u32()?));
+    }
+
+    let constant_count = self.u32()?;
+    let mut constants = Vec::new();
+    for _ in 0..constant_count {
+      let constant = match self.u8()? {
+        TAG_NUMBER => Constant::Number(self.f64()?),
+        TAG_STRING => Constant::String(self.string()?),
+        TAG_FUNCTION => Constant::Function(self.function(depth + 1)?),
+        tag => return Err(invalid(format!("unknown constant tag {tag}"))),
+      };
+
+      constants.push(constant);
+    }
+
+    if lines.iter().map(|&(_, run)| run).sum::<usize>() != code.len() {
+      return Err(invalid(format!("line table doesn't match code in {name:?}")));
+    }
+
+    let chunk = Chunk::from_parts(code, constants, lines);
+    validate_code(&chunk, &name, arity, upvalue_count)?;
+
+    Ok(Rc::new(Function {
+      name,
+      arity,
+      upvalue_count,
+      chunk,
+    }))
+  }
+}
+
+// Walks the code to make sure that every opcode is real, every operand is
+// there, every constant and upvalue it refers to exists, every jump lands on
+// an instruction, and that it can't run off the end. Then it checks what the
+// code does to the stack (see check_stack). The VM trusts all of that
+// completely; the one thing it checks for itself is that the values classes
+// and methods get built out of really are classes and methods, since there's
+// no knowing that from here.
+fn validate_code(
+  chunk: &Chunk,
+  name: &str,
+  arity: usize,
+  upvalue_count: usize,
+) -> Result<()> {
+  let code = &chunk.code;
+  let bad = |msg: String| invalid(format!("{msg} in {name:?}"));
+
+  // where each instruction starts, and where the one after it does
+  let mut starts = HashMap::new();
+  let mut jumps = vec![];
+  let mut last_op = None;
+  let mut offset = 0;
+
+  while offset < code.len() {
+    let op = OpCode::try_from(code[offset])
+      .map_err(|byte| bad(format!("unknown opcode {byte} at {offset}")))?;
+
+    let operand_len = match op {
+      OpCode::Constant
+      | OpCode::GetGlobal
+      | OpCode::DefineGlobal
+      | OpCode::SetGlobal
+      | OpCode::GetProperty
+      | OpCode::SetProperty
+      | OpCode::GetSuper
+      | OpCode::Class
+      | OpCode::Method
+      | OpCode::Closure
+      | OpCode::Jump
+      | OpCode::JumpIfFalse
+      | OpCode::Loop => 2,
+      OpCode::GetLocal
+      | OpCode::SetLocal
+      | OpCode::GetUpvalue
+      | OpCode::SetUpvalue
+      | OpCode::Call => 1,
+      _ => 0,
+    };
+
+    if offset + 1 + operand_len > code.len() {
+      return Err(bad(format!("truncated instruction at {offset}")));
+    }
+
+    let mut next = offset + 1 + operand_len;
+
+    match op {
+      OpCode::Constant => {
+        let idx = chunk.read_u16(offset + 1) as usize;
+        if idx >= chunk.constants.len() {
+          return Err(bad(format!("bad constant {idx} at {offset}")));
+        }
+      },
+      OpCode::GetGlobal
+      | OpCode::DefineGlobal
+      | OpCode::SetGlobal
+      | OpCode::GetProperty
+      | OpCode::SetProperty
+      | OpCode::GetSuper
+      | OpCode::Class
+      | OpCode::Method => {
+        let idx = chunk.read_u16(offset + 1) as usize;
+        if !matches!(chunk.constants.get(idx), Some(Constant::String(_))) {
+          return Err(bad(format!("expected a name constant at {offset}")));
+        }
+      },
+      OpCode::Closure => {
+        let idx = chunk.read_u16(offset + 1) as usize;
+        let Some(Constant::Function(f)) = chunk.constants.get(idx) else {
+          return Err(bad(format!("expected a function constant at {offset}")));
+        };
+
+        let captures = code
+          .get(next..next + f.upvalue_count * 2)
+          .ok_or_else(|| bad(format!("truncated closure at {offset}")))?;
+
+        for capture in captures.chunks(2) {
+          let (is_local, index) = (capture[0], capture[1] as usize);
+
+          if is_local > 1 || (is_local == 0 && index >= upvalue_count) {
+            return Err(bad(format!("bad upvalue capture at {offset}")));
+          }
+        }
+
+        next += captures.len();
+      },
+      OpCode::GetUpvalue | OpCode::SetUpvalue
+        if code[offset + 1] as usize >= upvalue_count =>
+      {
+        return Err(bad(format!("bad upvalue at {offset}")));
+      },
+      OpCode::Jump | OpCode::JumpIfFalse => {
+        jumps.push(next + chunk.read_u16(offset + 1) as usize);
+      },
+      OpCode::Loop => {
+        let target = next
+          .checked_sub(chunk.read_u16(offset + 1) as usize)
+          .ok_or_else(|| bad(format!("loop before start of code at {offset}")))?;
+        jumps.push(target);
+      },
+      _ => (),
+    }
+
+    starts.insert(offset, (op, next));
+    last_op = Some(op);
+    offset = next;
+  }
+
+  if last_op != Some(OpCode::Return) {
+    return Err(bad("code doesn't end with a return".into()));
+  }
+
+  for target in jumps {
+    if !starts.contains_key(&target) {
+      return Err(bad(format!("jump into the middle of nowhere ({target})")));
+    }
+  }
+
+  check_stack(chunk, &starts, arity).map_err(bad)
+}
+
+// Follows every path through the code, keeping count of how many values are
+// on the stack above the frame's base, so that nothing pops what isn't there
+// and no local slot gets touched before it exists. The frame starts out with
+// the function itself and its arguments. However an instruction is reached,
+// the count has to come out the same, which is how the compiler does it.
+fn check_stack(
+  chunk: &Chunk,
+  starts: &HashMap<usize, (OpCode, usize)>,
+  arity: usize,
+) -> std::result::Result<(), String> {
+  let mut heights = HashMap::new();
+  let mut paths = vec![(0, arity + 1)];
+
+  while let Some((offset, height)) = paths.pop() {
+    match heights.insert(offset, height) {
+      Some(seen) if seen == height => continue,
+      Some(seen) => {
+        return Err(format!("stack is {seen} or {height} deep at {offset}"));
+      },
+      None => (),
+    }
+
+    let (op, next) = starts[&offset];
+    let operand = || chunk.code[offset + 1] as usize;
+
+    // how many values the instruction needs there to be, and how many it
+    // leaves in their place
+    let (needs, leaves) = match op {
+      OpCode::Constant
+      | OpCode::Nil
+      | OpCode::True
+      | OpCode::False
+      | OpCode::GetGlobal
+      | OpCode::GetUpvalue
+      | OpCode::Closure
+      | OpCode::Class => (0, 1),
+      OpCode::GetLocal => {
+        if operand() >= height {
+          return Err(format!("bad local slot {} at {offset}", operand()));
+        }
+
+        (0, 1)
+      },
+      OpCode::SetLocal => {
+        if operand() >= height {
+          return Err(format!("bad local slot {} at {offset}", operand()));
+        }
+
+        (1, 1)
+      },
+      OpCode::Pop | OpCode::DefineGlobal | OpCode::Print | OpCode::CloseUpvalue => {
+        (1, 0)
+      },
+      OpCode::SetGlobal
+      | OpCode::SetUpvalue
+      | OpCode::GetProperty
+      | OpCode::Not
+      | OpCode::Negate
+      | OpCode::JumpIfFalse
+      | OpCode::Return => (1, 1),
+      OpCode::SetProperty
+      | OpCode::GetSuper
+      | OpCode::Equal
+      | OpCode::Greater
+      | OpCode::Less
+      | OpCode::Add
+      | OpCode::Subtract
+      | OpCode::Multiply
+      | OpCode::Divide
+      | OpCode::Inherit
+      | OpCode::Method => (2, 1),
+      OpCode::Call => (operand() + 1, 1),
+      OpCode::Jump | OpCode::Loop => (0, 0),
+    };
+
+    if height < needs {
+      return Err(format!("stack underflow at {offset}"));
+    }
+
+    if op == OpCode::Closure {
+      let captures = &chunk.code[offset + 3..next];
+
+      for capture in captures.chunks(2) {
+        if capture[0] == 1 && capture[1] as usize >= height {
+          return Err(format!("bad local capture {} at {offset}", capture[1]));
+        }
+      }
+    }
+
+    let height = height - needs + leaves;
+    let jump = || chunk.read_u16(offset + 1) as usize;
+
+    match op {
+      OpCode::Return => (),
+      OpCode::Jump => paths.push((next + jump(), height)),
+      OpCode::Loop => paths.push((next - jump(), height)),
+      OpCode::JumpIfFalse => {
+        paths.push((next + jump(), height));
+        paths.push((next, height));
+      },
+      _ => paths.push((next, height)),
+    }
+  }
+
+  Ok(())
+}
@@ -5,11 +5,12 @@ use crate::Token;
 pub enum Stmt {
   Empty,
   Block(Vec<Stmt>),
+  Class(Token, Option<Box<Expr>>, Vec<Stmt>), // the Vec is all Stmt::Function
   Expression(Box<Expr>),
   Function(Token, Vec<Token>, Vec<Stmt>),
   If(Box<Expr>, Box<Stmt>, Box<Stmt>),
   Print(Box<Expr>),
-  Return(Token, Box<Expr>),
-  Var(String, Box<Expr>), // maybe instead, Option<Expr>
-  While(Box<Expr>, Box<Stmt>),
+  Return(Token, Option<Box<Expr>>),
+  Var(Token, Box<Expr>), // maybe instead, Option<Expr>
+  While(Token, Box<Expr>, Box<Stmt>), // the token is the keyword
 }
@@ -0,0 +1,105 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+
+// An interned string. Names get looked up constantly, so rather than hashing
+// and comparing (and cloning) strings all over the place, the scanner turns
+// every identifier into one of these, and two symbols are the same name
+// exactly when their ids are the same. That's names only: string values in
+// the tree-walker are plain Rc<str>s, compared by contents.
+//
+// The table is per-thread and never shrinks: every distinct identifier that
+// gets scanned (or defined from Rust) stays in it, name and all, until the
+// thread exits. For scripts and REPL sessions that's a few hundred bytes, but
+// a host that evals an endless stream of code with fresh names in it will see
+// the table grow with every one. Looking a name up with Symbol::lookup never
+// adds to it.
+//
+// Since the table is per-thread, a symbol only means anything on the thread
+// that made it, so the marker keeps symbols (and anything holding one) from
+// being sent anywhere else.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32, PhantomData<*const ()>);
+
+// These are interned up front, in this order, so that the interpreter can
+// refer to them without going through the table.
+impl Symbol {
+  pub const THIS: Symbol = Symbol(0, PhantomData);
+  pub const SUPER: Symbol = Symbol(1, PhantomData);
+  pub const INIT: Symbol = Symbol(2, PhantomData);
+}
+
+const PREINTERNED: [&str; 3] = ["this", "super", "init"];
+
+struct Interner {
+  ids: HashMap<&'static str, Symbol>,
+  names: Vec<&'static str>,
+}
+
+thread_local! {
+  static INTERNER: RefCell<Interner> = RefCell::new(Interner::new());
+}
+
+impl Interner {
+  fn new() -> Self {
+    let mut interner = Interner {
+      ids: HashMap::new(),
+      names: vec![],
+    };
+
+    for name in PREINTERNED {
+      interner.intern(name);
+    }
+
+    interner
+  }
+
+  fn intern(&mut self, name: &str) -> Symbol {
+    if let Some(&sym) = self.ids.get(name) {
+      return sym;
+    }
+
+    // Leaking is what lets us hand out &'static strs; it's also why the
+    // table can't give anything back (see above).
+    let name: &'static str = Box::leak(name.into());
+    let sym = Symbol(self.names.len() as u32, PhantomData);
+
+    self.names.push(name);
+    self.ids.insert(name, sym);
+    sym
+  }
+}
+
+impl Symbol {
+  pub fn intern(name: &str) -> Symbol {
+    INTERNER.with(|interner| interner.borrow_mut().intern(name))
+  }
+
+  // The symbol for a name, if anything has ever used it, without interning
+  // it if not. A name nobody's interned can't be bound to anything anyway.
+  pub fn lookup(name: &str) -> Option<Symbol> {
+    INTERNER.with(|interner| interner.borrow().ids.get(name).copied())
+  }
+
+  pub fn as_str(self) -> &'static str {
+    INTERNER.with(|interner| interner.borrow().names[self.0 as usize])
+  }
+}
+
+impl From<&str> for Symbol {
+  fn from(name: &str) -> Self {
+    Symbol::intern(name)
+  }
+}
+
+impl std::fmt::Display for Symbol {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
+impl std::fmt::Debug for Symbol {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{:?}", self.as_str())
+  }
+}
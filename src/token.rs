@@ -1,5 +1,30 @@
+use std::rc::Rc;
+
+use crate::symbol::Symbol;
+
 type TT = TokenType;
 
+// The reserved words, and what new_identifier() turns each one into. Anything
+// not in here is an identifier.
+pub const KEYWORDS: [(&str, TokenType); 16] = [
+  ("and", TT::And),
+  ("class", TT::Class),
+  ("else", TT::Else),
+  ("false", TT::False),
+  ("fun", TT::Fun),
+  ("for", TT::For),
+  ("if", TT::If),
+  ("nil", TT::Nil),
+  ("or", TT::Or),
+  ("print", TT::Print),
+  ("return", TT::Return),
+  ("super", TT::Super),
+  ("this", TT::This),
+  ("true", TT::True),
+  ("var", TT::Var),
+  ("while", TT::While),
+];
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum TokenType {
   // single-char tokens
@@ -26,8 +51,8 @@ pub enum TokenType {
   LessEqual,
 
   // literals
-  Identifier(String),
-  String(String),
+  Identifier(Symbol),
+  String(Rc<str>),
   Number(f64),
 
   // keywords
@@ -48,40 +73,85 @@ pub enum TokenType {
   Var,
   While,
 
+  // something the scanner couldn't make sense of, and what was wrong with it
+  Error(String),
+
   EOF,
 }
 
-#[allow(unused)]
+// Where a token came from: start and end are byte offsets into the source
+// (end is exclusive), and line and column are where it starts, counting from
+// one. Columns count characters, not bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+  pub start: usize,
+  pub end: usize,
+  pub line: usize,
+  pub column: usize,
+}
+
 #[derive(Debug, Clone)]
 pub struct Token {
   pub kind: TokenType,
-  pub line: usize,
+  pub span: Span,
+}
+
+impl Span {
+  pub fn new(start: usize, end: usize, line: usize, column: usize) -> Self {
+    Span {
+      start,
+      end,
+      line,
+      column,
+    }
+  }
+
+  // for tokens that don't come from any real source
+  pub fn at_line(line: usize) -> Self {
+    Span::new(0, 0, line, 1)
+  }
 }
 
 impl Token {
-  pub fn new(kind: TokenType, line: usize) -> Self {
-    Token { kind, line }
+  pub fn new(kind: TokenType, span: Span) -> Self {
+    Token { kind, span }
+  }
+
+  pub fn line(&self) -> usize {
+    self.span.line
   }
 
   pub fn lexeme(&self) -> String {
     match &self.kind {
       TT::Number(n) => n.to_string(),
       TT::String(s) => format!("\"{s}\""),
-      TT::Identifier(s) => s.clone(),
+      TT::Identifier(s) => s.to_string(),
       _ => self.kind.as_str().to_string(),
     }
   }
 
+  // The name this token refers to, for identifiers and for the keywords that
+  // act like variables.
+  pub fn symbol(&self) -> Symbol {
+    match self.kind {
+      TT::Identifier(sym) => sym,
+      TT::This => Symbol::THIS,
+      TT::Super => Symbol::SUPER,
+      _ => panic!("{self} doesn't name anything"),
+    }
+  }
+
   pub fn kind_matches(&self, other: &TokenType) -> bool {
     use std::mem::discriminant;
     discriminant(&self.kind) == discriminant(other)
   }
 
   pub fn is_identifier(&self) -> bool {
-    match self.kind {
-      TT::Identifier(_) => true,
-      _ => false,
-    }
+    matches!(self.kind, TT::Identifier(_))
+  }
+
+  pub fn is_error(&self) -> bool {
+    matches!(self.kind, TT::Error(_))
   }
 }
 
@@ -93,25 +163,10 @@ impl std::fmt::Display for Token {
 
 impl TokenType {
   // returns an identifier or reserved word
-  pub fn new_identifier(s: String) -> Self {
-    match s.as_str() {
-      "and" => Self::And,
-      "class" => Self::Class,
-      "else" => Self::Else,
-      "false" => Self::False,
-      "fun" => Self::Fun,
-      "for" => Self::For,
-      "if" => Self::If,
-      "nil" => Self::Nil,
-      "or" => Self::Or,
-      "print" => Self::Print,
-      "return" => Self::Return,
-      "super" => Self::Super,
-      "this" => Self::This,
-      "true" => Self::True,
-      "var" => Self::Var,
-      "while" => Self::While,
-      _ => Self::Identifier(s),
+  pub fn new_identifier(s: &str) -> Self {
+    match KEYWORDS.iter().find(|(word, _)| *word == s) {
+      Some((_, kind)) => kind.clone(),
+      None => Self::Identifier(Symbol::intern(s)),
     }
   }
 
@@ -136,8 +191,8 @@ impl TokenType {
       TT::GreaterEqual => ">=",
       TT::Less => "<",
       TT::LessEqual => "<=>",
-      TT::Identifier(s) => &s,
-      TT::String(s) => &s,
+      TT::Identifier(s) => s.as_str(),
+      TT::String(s) => s,
       TT::Number(_) => "__SOME NUMBER__", // lol what
       TT::And => "and",
       TT::Class => "class",
@@ -155,6 +210,7 @@ impl TokenType {
       TT::True => "true",
       TT::Var => "var",
       TT::While => "while",
+      TT::Error(_) => "error",
       TT::EOF => "eof",
     }
   }
@@ -1,24 +1,36 @@
 pub mod ast_printer {
   use crate::expr::Expr;
 
-  pub fn print_ast(expr: Box<Expr>) {
+  pub fn print_ast(expr: &Expr) {
     println!("{}", to_string(expr));
   }
 
-  fn to_string(expr: Box<Expr>) -> String {
-    match *expr {
-      Expr::Binary(left, op, right) => parenthesize(&op.lexeme(), vec![left, right]),
-      Expr::Grouping(e) => parenthesize("group", vec![e]),
-      Expr::Unary(op, right) => parenthesize(&op.lexeme(), vec![right]),
+  fn to_string(expr: &Expr) -> String {
+    match expr {
+      Expr::Binary(left, op, right) => parenthesize(&op.lexeme(), &[left, right]),
+      Expr::Grouping(e) => parenthesize("group", &[e]),
+      Expr::Unary(op, right) => parenthesize(&op.lexeme(), &[right]),
       Expr::Literal(val) => format!("{val}"),
-      Expr::Variable(name) => format!("var {name}"),
-      Expr::Assign(_tok, _expr) => todo!(),
-      Expr::Logical(_left, _op, _right) => todo!(),
-      Expr::Call(_callee, _paren, _args) => todo!(),
+      Expr::Variable(_, name) => name.lexeme(),
+      Expr::Assign(_, name, value) => {
+        parenthesize(&format!("assign {}", name.lexeme()), &[value])
+      },
+      Expr::Logical(left, op, right) => parenthesize(&op.lexeme(), &[left, right]),
+      Expr::Call(callee, _, args) => {
+        let mut exprs = vec![callee.as_ref()];
+        exprs.extend(args.iter().map(|arg| arg.as_ref()));
+        parenthesize("call", &exprs)
+      },
+      Expr::Get(object, name) => parenthesize(&format!("get {}", name.lexeme()), &[object]),
+      Expr::Set(object, name, value) => {
+        parenthesize(&format!("set {}", name.lexeme()), &[object, value])
+      },
+      Expr::Super(_, _, method) => format!("(super {})", method.lexeme()),
+      Expr::This(_, _) => "this".into(),
     }
   }
 
-  fn parenthesize(name: &str, exprs: Vec<Box<Expr>>) -> String {
+  fn parenthesize(name: &str, exprs: &[&Expr]) -> String {
     let mut s = String::from("(");
     s.push_str(name);
 
@@ -1,17 +1,29 @@
 mod callable;
+mod class;
+mod convert;
+mod function;
+mod signature;
+
+use std::rc::Rc;
 
 use crate::expr::Literal;
-use crate::{Error, Interpreter, Result};
+use crate::{Interpreter, Result};
 pub use callable::Callable;
+pub use class::{InstanceRef, LoxClass, LoxInstance};
+pub use convert::{FromLoxArgs, FromLoxValue};
+pub use function::LoxFunction;
+pub use signature::{Param, ParamType, Signature};
 
 // This is framework I suspect I will need, but am shoving in here for
 // expediency and I'll move it later.
 #[derive(Debug, Clone)]
 pub enum LoxValue {
   Number(f64),
-  String(String),
+  String(Rc<str>),
   Boolean(bool),
-  Function(Box<Callable>),
+  Function(Rc<Callable>),
+  Class(Rc<LoxClass>),
+  Instance(InstanceRef),
   Nil,
 }
 
@@ -19,7 +31,20 @@ pub type Func = dyn Fn(&mut Interpreter, Vec<LoxValue>) -> Result<LoxValue>;
 
 impl LoxValue {
   pub fn new_callable(name: String, arity: usize, func: Box<Func>) -> Self {
-    LoxValue::Function(Box::new(Callable::new(name, arity, func)))
+    LoxValue::Function(Rc::new(Callable::new(name, arity, func)))
+  }
+
+  pub fn new_variadic(
+    name: String,
+    min_arity: usize,
+    max_arity: Option<usize>,
+    func: Box<Func>,
+  ) -> Self {
+    LoxValue::Function(Rc::new(Callable::variadic(name, min_arity, max_arity, func)))
+  }
+
+  pub fn new_native(name: String, signature: Signature, func: Box<Func>) -> Self {
+    LoxValue::Function(Rc::new(Callable::with_signature(name, signature, func)))
   }
 
   pub fn is_truthy(&self) -> bool {
@@ -31,11 +56,7 @@ impl LoxValue {
   }
 
   pub fn is_number(&self) -> bool {
-    if let Self::Number(_) = self {
-      true
-    } else {
-      false
-    }
+    matches!(self, Self::Number(_))
   }
 
   pub fn as_number(&self) -> f64 {
@@ -47,10 +68,7 @@ impl LoxValue {
   }
 
   pub fn is_callable(&self) -> bool {
-    match self {
-      Self::Function(_) => true,
-      _ => false,
-    }
+    matches!(self, Self::Function(_) | Self::Class(_))
   }
 
   pub fn as_callable(&self) -> &Callable {
@@ -60,6 +78,19 @@ impl LoxValue {
     }
   }
 
+  // what sort of value this is, for error messages
+  pub fn type_name(&self) -> &'static str {
+    match self {
+      Self::Number(_) => "number",
+      Self::String(_) => "string",
+      Self::Boolean(_) => "boolean",
+      Self::Function(_) => "function",
+      Self::Class(_) => "class",
+      Self::Instance(_) => "instance",
+      Self::Nil => "nil",
+    }
+  }
+
   pub fn type_matches(&self, other: &Self) -> bool {
     use std::mem::discriminant;
     discriminant(self) == discriminant(other)
@@ -77,18 +108,6 @@ impl From<Literal> for LoxValue {
   }
 }
 
-impl TryFrom<LoxValue> for f64 {
-  type Error = Error;
-
-  fn try_from(lv: LoxValue) -> Result<f64> {
-    if let LoxValue::Number(n) = lv {
-      Ok(n)
-    } else {
-      Err(Error::TryFrom(format!("{:?} is not a number", lv)))
-    }
-  }
-}
-
 impl std::fmt::Display for LoxValue {
   fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
     match self {
@@ -96,6 +115,8 @@ impl std::fmt::Display for LoxValue {
       LoxValue::String(s) => write!(f, "{}", s),
       LoxValue::Boolean(b) => write!(f, "{}", b),
       LoxValue::Function(c) => write!(f, "<function {}>", c.name),
+      LoxValue::Class(c) => write!(f, "<class {}>", c.name),
+      LoxValue::Instance(i) => write!(f, "<{} instance>", i.borrow().class_name()),
       LoxValue::Nil => write!(f, "nil"),
     }
   }
@@ -107,9 +128,16 @@ impl std::cmp::PartialEq for LoxValue {
 
     match (self, other) {
       (LV::Number(a), LV::Number(b)) => a == b,
-      (LV::String(a), LV::String(b)) => a == b,
+      // String values aren't interned here, only names are (see symbol.rs).
+      // A literal compared with itself (the same bit of source, say, each time
+      // round a loop) shares its contents and gets away with a pointer check,
+      // but literals from different places, results of +, and strings made
+      // from Rust all get their contents compared. The VM does intern them.
+      (LV::String(a), LV::String(b)) => Rc::ptr_eq(a, b) || a == b,
       (LV::Boolean(a), LV::Boolean(b)) => a == b,
       (LV::Function(_), LV::Function(_)) => false, // functions are never equal
+      (LV::Class(a), LV::Class(b)) => Rc::ptr_eq(a, b),
+      (LV::Instance(a), LV::Instance(b)) => Rc::ptr_eq(a, b),
       (LV::Nil, LV::Nil) => true,
       _ => false,
     }
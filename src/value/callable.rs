@@ -1,22 +1,79 @@
-use std::sync::Arc;
+use std::rc::Rc;
 
-use crate::value::{Func, LoxValue};
+use crate::gc::{Trace, Tracer};
+use crate::value::{Func, LoxFunction, LoxValue, Signature};
 use crate::{Interpreter, Result};
 
 #[derive(Clone)]
 pub struct Callable {
-  pub name: String,
-  pub arity: usize,
-  // this Arc is just so that I can implement Clone, which I need to do for Reasons.
-  func: Arc<Box<Func>>,
+  pub name: Rc<str>,
+  pub min_arity: usize,
+  pub max_arity: Option<usize>, // None if it'll take any number
+  // natives declared with one get their arguments checked before they're
+  // called; otherwise, all we check is how many there are
+  signature: Option<Rc<Signature>>,
+  code: Code,
+}
+
+// What actually runs: either a Rust closure, or a function from Lox code. The
+// Lox ones stay as they are, rather than getting wrapped up in a closure, so
+// that the garbage collector can see the environment they've closed over.
+#[derive(Clone)]
+enum Code {
+  // this Rc is just so that I can implement Clone, which I need to do for Reasons.
+  Native(Rc<Box<Func>>),
+  Lox(LoxFunction),
 }
 
 impl Callable {
   pub fn new(name: String, arity: usize, func: Box<Func>) -> Callable {
+    Callable::variadic(name, arity, Some(arity), func)
+  }
+
+  pub fn variadic(
+    name: String,
+    min_arity: usize,
+    max_arity: Option<usize>,
+    func: Box<Func>,
+  ) -> Callable {
+    Callable {
+      min_arity,
+      max_arity,
+      name: name.into(),
+      signature: None,
+      code: Code::Native(Rc::new(func)),
+    }
+  }
+
+  pub fn with_signature(name: String, signature: Signature, func: Box<Func>) -> Callable {
+    Callable {
+      min_arity: signature.min_arity(),
+      max_arity: signature.max_arity(),
+      name: name.into(),
+      signature: Some(Rc::new(signature)),
+      code: Code::Native(Rc::new(func)),
+    }
+  }
+
+  pub fn lox(func: LoxFunction) -> Callable {
     Callable {
-      arity,
-      name,
-      func: Arc::new(func),
+      min_arity: func.arity(),
+      max_arity: Some(func.arity()),
+      name: func.name.clone(),
+      signature: None,
+      code: Code::Lox(func),
+    }
+  }
+
+  pub fn signature(&self) -> Option<&Signature> {
+    self.signature.as_deref()
+  }
+
+  // the function underneath, if this came from Lox code rather than Rust
+  pub fn lox_function(&self) -> Option<&LoxFunction> {
+    match &self.code {
+      Code::Lox(func) => Some(func),
+      Code::Native(_) => None,
     }
   }
 
@@ -25,7 +82,22 @@ impl Callable {
     interp: &mut Interpreter,
     args: Vec<LoxValue>,
   ) -> Result<LoxValue> {
-    (self.func)(interp, args)
+    match &self.code {
+      Code::Native(func) => func(interp, args),
+      Code::Lox(func) => func.call(interp, args),
+    }
+  }
+}
+
+impl Trace for Callable {
+  fn trace(&self, tracer: &mut Tracer) {
+    if let Code::Lox(func) = &self.code {
+      func.trace(tracer);
+    }
+  }
+
+  fn size(&self) -> usize {
+    std::mem::size_of::<Self>()
   }
 }
 
@@ -0,0 +1,151 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::gc::{self, Trace, Tracer};
+use crate::symbol::Symbol;
+use crate::value::{LoxFunction, LoxValue};
+use crate::{Error, Interpreter, Result, Token};
+
+pub type InstanceRef = Rc<RefCell<LoxInstance>>;
+
+pub struct LoxClass {
+  pub name: Rc<str>,
+  superclass: Option<Rc<LoxClass>>,
+  methods: HashMap<Symbol, LoxFunction>,
+}
+
+pub struct LoxInstance {
+  class: Rc<LoxClass>,
+  fields: HashMap<Symbol, LoxValue>,
+}
+
+impl LoxClass {
+  pub fn new(
+    name: String,
+    superclass: Option<Rc<LoxClass>>,
+    methods: HashMap<Symbol, LoxFunction>,
+  ) -> Rc<Self> {
+    let class = Rc::new(LoxClass {
+      name: name.into(),
+      superclass,
+      methods,
+    });
+
+    gc::track_class(&class);
+    class
+  }
+
+  // looks in this class first, then on up the inheritance chain
+  pub fn find_method(&self, name: Symbol) -> Option<&LoxFunction> {
+    self.methods.get(&name).or_else(|| {
+      self
+        .superclass
+        .as_ref()
+        .and_then(|superclass| superclass.find_method(name))
+    })
+  }
+
+  // a class's arity is whatever its initializer says it is
+  pub fn arity(&self) -> usize {
+    self.find_method(Symbol::INIT).map_or(0, |init| init.arity())
+  }
+
+  pub fn instantiate(
+    class: &Rc<LoxClass>,
+    interp: &mut Interpreter,
+    args: Vec<LoxValue>,
+  ) -> Result<LoxValue> {
+    let instance = Rc::new(RefCell::new(LoxInstance {
+      class: Rc::clone(class),
+      fields: HashMap::new(),
+    }));
+
+    gc::track_instance(&instance);
+
+    if let Some(init) = class.find_method(Symbol::INIT) {
+      init.bind(&instance).call(interp, args)?;
+    }
+
+    Ok(LoxValue::Instance(instance))
+  }
+}
+
+impl LoxInstance {
+  // Fields shadow methods; methods come back bound to this instance.
+  pub fn get(instance: &InstanceRef, name: &Token) -> Result<LoxValue> {
+    let sym = name.symbol();
+    let this = instance.borrow();
+
+    if let Some(val) = this.fields.get(&sym) {
+      return Ok(val.clone());
+    }
+
+    match this.class.find_method(sym) {
+      Some(method) => Ok(method.bind(instance).into_value()),
+      None => Err(Error::Runtime(
+        name.clone(),
+        format!("Undefined property '{sym}'."),
+      )),
+    }
+  }
+
+  pub fn set(&mut self, name: &Token, value: LoxValue) {
+    if self.fields.insert(name.symbol(), value).is_none() {
+      gc::grew(gc::ENTRY_SIZE);
+    }
+  }
+
+  // for the collector, when it's breaking cycles
+  pub(crate) fn take_fields(&mut self) -> HashMap<Symbol, LoxValue> {
+    std::mem::take(&mut self.fields)
+  }
+
+  pub fn class_name(&self) -> &str {
+    &self.class.name
+  }
+}
+
+impl Trace for LoxClass {
+  fn trace(&self, tracer: &mut Tracer) {
+    if let Some(superclass) = &self.superclass {
+      tracer.class(superclass);
+    }
+
+    for method in self.methods.values() {
+      method.trace(tracer);
+    }
+  }
+
+  fn size(&self) -> usize {
+    std::mem::size_of::<Self>() + self.methods.len() * gc::ENTRY_SIZE
+  }
+}
+
+impl Trace for LoxInstance {
+  fn trace(&self, tracer: &mut Tracer) {
+    tracer.class(&self.class);
+
+    for value in self.fields.values() {
+      tracer.value(value);
+    }
+  }
+
+  fn size(&self) -> usize {
+    std::mem::size_of::<Self>() + self.fields.len() * gc::ENTRY_SIZE
+  }
+}
+
+// These are written out by hand, because an instance can easily (and a class
+// will always, via its methods' closures) end up containing itself.
+impl std::fmt::Debug for LoxClass {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "<class {}>", self.name)
+  }
+}
+
+impl std::fmt::Debug for LoxInstance {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "<{} instance>", self.class.name)
+  }
+}
@@ -0,0 +1,232 @@
+use crate::value::LoxValue;
+use crate::{Error, Result};
+
+// Conversions between Lox values and plain Rust ones, for getting things in
+// and out of the interpreter. Into LoxValue always works; out of one only
+// works if the value is the right sort (and, for integers, actually is a
+// whole number that fits), and otherwise gives back an Error::TryFrom.
+//
+// Option<T> is nil or a T, and () is just nil. Lox doesn't have lists (yet),
+// so there's nothing for Vec<T> to turn into.
+
+impl From<f64> for LoxValue {
+  fn from(n: f64) -> Self {
+    LoxValue::Number(n)
+  }
+}
+
+impl From<f32> for LoxValue {
+  fn from(n: f32) -> Self {
+    LoxValue::Number(n.into())
+  }
+}
+
+impl From<String> for LoxValue {
+  fn from(s: String) -> Self {
+    LoxValue::String(s.into())
+  }
+}
+
+impl From<&str> for LoxValue {
+  fn from(s: &str) -> Self {
+    LoxValue::String(s.into())
+  }
+}
+
+impl From<bool> for LoxValue {
+  fn from(b: bool) -> Self {
+    LoxValue::Boolean(b)
+  }
+}
+
+impl From<()> for LoxValue {
+  fn from(_: ()) -> Self {
+    LoxValue::Nil
+  }
+}
+
+impl<T: Into<LoxValue>> From<Option<T>> for LoxValue {
+  fn from(opt: Option<T>) -> Self {
+    opt.map_or(LoxValue::Nil, Into::into)
+  }
+}
+
+// The other way, for anything that a Lox value might turn into. The plain
+// types all implement TryFrom<LoxValue> as well; this exists so that Option
+// can join in, which the blanket impls in std won't let it do with TryFrom.
+pub trait FromLoxValue: Sized {
+  fn from_lox_value(value: LoxValue) -> Result<Self>;
+}
+
+impl FromLoxValue for LoxValue {
+  fn from_lox_value(value: LoxValue) -> Result<Self> {
+    Ok(value)
+  }
+}
+
+impl FromLoxValue for f64 {
+  fn from_lox_value(value: LoxValue) -> Result<Self> {
+    match value {
+      LoxValue::Number(n) => Ok(n),
+      _ => Err(mismatch("a number", &value)),
+    }
+  }
+}
+
+impl FromLoxValue for String {
+  fn from_lox_value(value: LoxValue) -> Result<Self> {
+    match value {
+      LoxValue::String(s) => Ok(s.to_string()),
+      _ => Err(mismatch("a string", &value)),
+    }
+  }
+}
+
+impl FromLoxValue for bool {
+  fn from_lox_value(value: LoxValue) -> Result<Self> {
+    match value {
+      LoxValue::Boolean(b) => Ok(b),
+      _ => Err(mismatch("a boolean", &value)),
+    }
+  }
+}
+
+impl FromLoxValue for () {
+  fn from_lox_value(value: LoxValue) -> Result<Self> {
+    match value {
+      LoxValue::Nil => Ok(()),
+      _ => Err(mismatch("nil", &value)),
+    }
+  }
+}
+
+impl<T: FromLoxValue> FromLoxValue for Option<T> {
+  fn from_lox_value(value: LoxValue) -> Result<Self> {
+    match value {
+      LoxValue::Nil => Ok(None),
+      value => T::from_lox_value(value).map(Some),
+    }
+  }
+}
+
+// Numbers are all f64s, so integers go in as those, and only come back out
+// if they're whole and in range.
+macro_rules! impl_integer {
+  ($($int:ty),*) => {
+    $(
+      impl From<$int> for LoxValue {
+        fn from(n: $int) -> Self {
+          LoxValue::Number(n as f64)
+        }
+      }
+
+      impl FromLoxValue for $int {
+        fn from_lox_value(value: LoxValue) -> Result<Self> {
+          let what = concat!("a whole number that fits in ", stringify!($int));
+          // MAX as f64 rounds up to 2^63/2^64 for the 64-bit types, so compare
+          // against the exclusive bound 2^BITS (unsigned) or 2^(BITS-1) (signed)
+          let bits = <$int>::BITS - if <$int>::MIN == 0 { 0 } else { 1 };
+          let (min, end) = (<$int>::MIN as f64, 2f64.powi(bits as i32));
+
+          match value {
+            LoxValue::Number(n) if n.fract() == 0.0 && n >= min && n < end => {
+              Ok(n as $int)
+            },
+            LoxValue::Number(n) => {
+              Err(Error::TryFrom(format!("expected {what}, got {n}")))
+            },
+            _ => Err(mismatch(what, &value)),
+          }
+        }
+      }
+    )*
+  };
+}
+
+impl_integer!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+macro_rules! impl_try_from {
+  ($($ty:ty),*) => {
+    $(
+      impl TryFrom<LoxValue> for $ty {
+        type Error = Error;
+
+        fn try_from(value: LoxValue) -> Result<Self> {
+          <$ty>::from_lox_value(value)
+        }
+      }
+    )*
+  };
+}
+
+impl_try_from!(f64, String, bool, ());
+impl_try_from!(i8, i16, i32, i64, isize, u8, u16, u32, u64, usize);
+
+fn mismatch(expected: &str, got: &LoxValue) -> Error {
+  Error::TryFrom(format!("expected {expected}, got {}", got.type_name()))
+}
+
+// For natives that would rather have their arguments as a tuple of Rust
+// values than a Vec of Lox ones:
+//
+//   let (name, times): (String, u32) = FromLoxArgs::from_lox_args(args)?;
+//
+// Missing arguments count as nil (so they're fine for Options), and any that
+// don't convert give an error saying which one it was, worded to follow the
+// function's name the way signature errors are ("expects a number for
+// argument 1, got string"). When that happens inside a call, the interpreter
+// puts the name in front and makes it a runtime error at the call.
+pub trait FromLoxArgs: Sized {
+  fn from_lox_args(args: Vec<LoxValue>) -> Result<Self>;
+}
+
+macro_rules! impl_from_lox_args {
+  ($count:literal: $($arg:ident),*) => {
+    impl<$($arg),*> FromLoxArgs for ($($arg,)*)
+    where
+      $($arg: FromLoxValue,)*
+    {
+      fn from_lox_args(args: Vec<LoxValue>) -> Result<Self> {
+        if args.len() > $count {
+          return Err(Error::TryFrom(format!(
+            "expects at most {} arguments, got {}",
+            $count,
+            args.len()
+          )));
+        }
+
+        let mut _args = args.into_iter();
+        let mut _position = 0;
+
+        Ok(($({
+          _position += 1;
+          let arg = _args.next().unwrap_or(LoxValue::Nil);
+          $arg::from_lox_value(arg).map_err(|err| match err {
+            Error::TryFrom(msg) => Error::TryFrom(for_argument(_position, &msg)),
+            err => err,
+          })?
+        },)*))
+      }
+    }
+  };
+}
+
+// Rewords what FromLoxValue said went wrong ("expected a number, got string")
+// to say which argument it was. Conversions from elsewhere that don't word it
+// like that just get the position tacked on.
+fn for_argument(position: usize, msg: &str) -> String {
+  let parts = msg.strip_prefix("expected ").and_then(|msg| msg.rsplit_once(", got "));
+
+  match parts {
+    Some((what, got)) => format!("expects {what} for argument {position}, got {got}"),
+    None => format!("can't take argument {position}: {msg}"),
+  }
+}
+
+impl_from_lox_args!(0:);
+impl_from_lox_args!(1: A);
+impl_from_lox_args!(2: A, B);
+impl_from_lox_args!(3: A, B, C);
+impl_from_lox_args!(4: A, B, C, D);
+impl_from_lox_args!(5: A, B, C, D, E);
+impl_from_lox_args!(6: A, B, C, D, E, G);
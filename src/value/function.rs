@@ -0,0 +1,94 @@
+use std::rc::Rc;
+
+use crate::environment::{EnvRef, Environment};
+use crate::gc::{self, Tracer};
+use crate::stmt::Stmt;
+use crate::symbol::Symbol;
+use crate::value::{Callable, InstanceRef, LoxValue};
+use crate::{Error, Interpreter, Result, Token};
+
+// A function declared in Lox code (as opposed to a native one), along with
+// the environment it was declared in. Methods are these too; binding one to
+// an instance just wraps its closure in a new environment that has `this`.
+#[derive(Clone)]
+pub struct LoxFunction {
+  pub name: Rc<str>,
+  params: Rc<Vec<Token>>,
+  body: Rc<Vec<Stmt>>,
+  closure: EnvRef,
+  is_initializer: bool,
+}
+
+impl LoxFunction {
+  pub fn new(
+    name: String,
+    params: &[Token],
+    body: &[Stmt],
+    closure: EnvRef,
+    is_initializer: bool,
+  ) -> Self {
+    LoxFunction {
+      name: name.into(),
+      params: Rc::new(params.to_vec()),
+      body: Rc::new(body.to_vec()),
+      closure,
+      is_initializer,
+    }
+  }
+
+  pub fn arity(&self) -> usize {
+    self.params.len()
+  }
+
+  pub fn bind(&self, instance: &InstanceRef) -> LoxFunction {
+    let env = Environment::new_enclosing(&self.closure);
+    env
+      .borrow_mut()
+      .define(Symbol::THIS, LoxValue::Instance(Rc::clone(instance)));
+
+    LoxFunction {
+      closure: env,
+      ..self.clone()
+    }
+  }
+
+  pub fn call(&self, interp: &mut Interpreter, args: Vec<LoxValue>) -> Result<LoxValue> {
+    let env = Environment::new_enclosing(&self.closure);
+
+    for (param, arg) in self.params.iter().zip(args) {
+      env.borrow_mut().define(param.symbol(), arg);
+    }
+
+    let retval = match interp.execute_block(&self.body, env) {
+      Ok(()) => LoxValue::Nil,
+      Err(Error::Return(retval)) => retval,
+      Err(e) => return Err(e),
+    };
+
+    // initializers always hand back the instance, whatever happened inside
+    if self.is_initializer {
+      Ok(self.closure.borrow().get_here(Symbol::THIS).unwrap_or(LoxValue::Nil))
+    } else {
+      Ok(retval)
+    }
+  }
+
+  // what it's closed over is all the collector needs to know about
+  pub(crate) fn trace(&self, tracer: &mut Tracer) {
+    tracer.env(&self.closure);
+  }
+
+  // Wraps this up as a callable value, so that the interpreter can treat it
+  // just like any other function.
+  pub fn into_value(self) -> LoxValue {
+    let function = Rc::new(Callable::lox(self));
+    gc::track_function(&function);
+    LoxValue::Function(function)
+  }
+}
+
+impl std::fmt::Debug for LoxFunction {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "<function {}>", self.name)
+  }
+}
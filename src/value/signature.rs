@@ -0,0 +1,107 @@
+use crate::value::LoxValue;
+
+// What a native function takes, so that the interpreter can check calls to it
+// up front, and the function itself can just get on with things. Optional
+// parameters come after the required ones (and can be left off, or given
+// nil), and a variadic one (which takes any number of arguments, including
+// none) can only come last.
+#[derive(Debug, Clone)]
+pub struct Signature {
+  params: Vec<Param>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Param {
+  Required(ParamType),
+  Optional(ParamType),
+  Variadic(ParamType),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParamType {
+  Number,
+  String,
+  Boolean,
+  Any,
+}
+
+impl Signature {
+  pub fn new(params: Vec<Param>) -> Self {
+    for (i, pair) in params.windows(2).enumerate() {
+      match pair {
+        [Param::Variadic(_), _] => panic!("variadic parameter {} isn't last", i + 1),
+        [Param::Optional(_), Param::Required(_)] => {
+          panic!("required parameter {} follows an optional one", i + 2)
+        },
+        _ => (),
+      }
+    }
+
+    Signature { params }
+  }
+
+  pub fn params(&self) -> &[Param] {
+    &self.params
+  }
+
+  pub fn min_arity(&self) -> usize {
+    self.params.iter().filter(|p| matches!(p, Param::Required(_))).count()
+  }
+
+  // None if there's no limit
+  pub fn max_arity(&self) -> Option<usize> {
+    match self.params.last() {
+      Some(Param::Variadic(_)) => None,
+      _ => Some(self.params.len()),
+    }
+  }
+
+  // The interpreter checks the number of arguments against min_arity() and
+  // max_arity() before this, along with everything else it calls.
+  pub fn check_types(&self, name: &str, args: &[LoxValue]) -> Result<(), String> {
+    for (i, arg) in args.iter().enumerate() {
+      let param = self.params.get(i).or(self.params.last());
+
+      let expected = match param {
+        Some(Param::Required(ty) | Param::Optional(ty) | Param::Variadic(ty)) => ty,
+        None => continue,
+      };
+
+      if matches!(param, Some(Param::Optional(_))) && *arg == LoxValue::Nil {
+        continue;
+      }
+
+      if !expected.accepts(arg) {
+        let got = arg.type_name();
+        let position = i + 1;
+        return Err(format!(
+          "{name} expects {expected} for argument {position}, got {got}"
+        ));
+      }
+    }
+
+    Ok(())
+  }
+}
+
+impl ParamType {
+  pub fn accepts(self, value: &LoxValue) -> bool {
+    match self {
+      ParamType::Number => matches!(value, LoxValue::Number(_)),
+      ParamType::String => matches!(value, LoxValue::String(_)),
+      ParamType::Boolean => matches!(value, LoxValue::Boolean(_)),
+      ParamType::Any => true,
+    }
+  }
+}
+
+impl std::fmt::Display for ParamType {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      ParamType::Number => write!(f, "a number"),
+      ParamType::String => write!(f, "a string"),
+      ParamType::Boolean => write!(f, "a boolean"),
+      ParamType::Any => write!(f, "anything"),
+    }
+  }
+}
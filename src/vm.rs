@@ -0,0 +1,699 @@
+mod heap;
+mod object;
+mod value;
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::chunk::{Constant, Function, OpCode};
+use crate::gc::GcConfig;
+use crate::{Error, Result, MAX_CALL_DEPTH};
+use heap::{Heap, ObjRef};
+use object::*;
+use value::Value;
+
+// the script's frame, plus as many calls as the tree-walker allows
+const FRAMES_MAX: usize = MAX_CALL_DEPTH + 1;
+
+#[derive(Debug)]
+struct CallFrame {
+  closure: ObjRef,
+  // these two are copied out of the closure's function, so that we don't have
+  // to go through the heap for every instruction
+  proto: Rc<Function>,
+  constants: Rc<[Value]>,
+  ip: usize,
+  slots: usize, // where this frame's window onto the stack starts
+}
+
+// A stack-based virtual machine that runs the compiler's bytecode. This is
+// meant to behave exactly like the tree-walking Interpreter, just faster.
+#[derive(Debug)]
+pub struct Vm {
+  heap: Heap,
+  stack: Vec<Value>,
+  frames: Vec<CallFrame>,
+  globals: HashMap<ObjRef, Value>,
+  open_upvalues: Vec<ObjRef>,
+  init_string: ObjRef,
+}
+
+impl Default for Vm {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Vm {
+  pub fn new() -> Self {
+    Vm::with_gc_config(GcConfig::default())
+  }
+
+  pub fn with_gc_config(config: GcConfig) -> Self {
+    let mut heap = Heap::with_config(config);
+    let init_string = heap.intern("init");
+
+    let mut vm = Vm {
+      heap,
+      stack: Vec::with_capacity(FRAMES_MAX * 256),
+      frames: Vec::with_capacity(FRAMES_MAX),
+      globals: HashMap::new(),
+      open_upvalues: vec![],
+      init_string,
+    };
+
+    vm.define_native("clock", 0, clock);
+
+    vm
+  }
+
+  // The names of all the globals, with their values as print would show them.
+  pub fn globals(&self) -> Vec<(String, String)> {
+    self
+      .globals
+      .iter()
+      .map(|(&name, &value)| (self.heap.string(name).to_string(), self.heap.format(value)))
+      .collect()
+  }
+
+  pub fn interpret(&mut self, function: Rc<Function>) -> Result<()> {
+    let function = self.load_function(&function);
+    let closure = self.heap.alloc(Obj::Closure(ObjClosure {
+      function,
+      upvalues: vec![],
+    }));
+
+    self.push(Value::Obj(closure));
+    self.call(closure, 0)?;
+    self.run()
+  }
+
+  fn run(&mut self) -> Result<()> {
+    loop {
+      // Only ever collect in between instructions: at that point everything
+      // the program can still get at is reachable from the roots.
+      if self.heap.should_collect() {
+        self.collect_garbage();
+      }
+
+      let byte = self.read_byte();
+      let Ok(op) = OpCode::try_from(byte) else {
+        return Err(self.runtime_error(format!("unknown opcode {byte}")));
+      };
+
+      match op {
+        OpCode::Constant => {
+          let constant = self.read_constant();
+          self.push(constant);
+        },
+        OpCode::Nil => self.push(Value::Nil),
+        OpCode::True => self.push(Value::Boolean(true)),
+        OpCode::False => self.push(Value::Boolean(false)),
+        OpCode::Pop => {
+          self.pop();
+        },
+
+        // variables
+        OpCode::GetLocal => {
+          let slot = self.read_byte() as usize;
+          let base = self.frame().slots;
+          self.push(self.stack[base + slot]);
+        },
+        OpCode::SetLocal => {
+          let slot = self.read_byte() as usize;
+          let base = self.frame().slots;
+          self.stack[base + slot] = self.peek(0);
+        },
+        OpCode::GetGlobal => {
+          let name = self.read_string();
+
+          match self.globals.get(&name) {
+            Some(&value) => self.push(value),
+            None => return Err(self.undefined_variable(name)),
+          }
+        },
+        OpCode::DefineGlobal => {
+          let name = self.read_string();
+          self.globals.insert(name, self.peek(0));
+          self.pop();
+        },
+        OpCode::SetGlobal => {
+          let name = self.read_string();
+
+          if !self.globals.contains_key(&name) {
+            return Err(self.undefined_variable(name));
+          }
+
+          self.globals.insert(name, self.peek(0));
+        },
+        OpCode::GetUpvalue => {
+          let idx = self.read_byte() as usize;
+          let upvalue = self.heap.closure(self.frame().closure).upvalues[idx];
+
+          let value = match self.heap.upvalue_mut(upvalue) {
+            ObjUpvalue::Open(slot) => self.stack[*slot],
+            ObjUpvalue::Closed(value) => *value,
+          };
+
+          self.push(value);
+        },
+        OpCode::SetUpvalue => {
+          let idx = self.read_byte() as usize;
+          let upvalue = self.heap.closure(self.frame().closure).upvalues[idx];
+          let value = self.peek(0);
+
+          match self.heap.upvalue_mut(upvalue) {
+            ObjUpvalue::Open(slot) => self.stack[*slot] = value,
+            ObjUpvalue::Closed(closed) => *closed = value,
+          }
+        },
+
+        // properties
+        OpCode::GetProperty => {
+          let name = self.read_string();
+
+          let Some((class, field)) = self.as_instance(self.peek(0)).map(|instance| {
+            (instance.class, instance.fields.get(&name).copied())
+          }) else {
+            return Err(self.runtime_error("Only instances have properties."));
+          };
+
+          match field {
+            Some(value) => {
+              self.pop();
+              self.push(value);
+            },
+            None => self.bind_method(class, name)?,
+          }
+        },
+        OpCode::SetProperty => {
+          let name = self.read_string();
+          let value = self.peek(0);
+
+          let Value::Obj(handle) = self.peek(1) else {
+            return Err(self.runtime_error("Only instances have fields."));
+          };
+
+          match self.heap.get_mut(handle) {
+            Obj::Instance(instance) => instance.fields.insert(name, value),
+            _ => return Err(self.runtime_error("Only instances have fields.")),
+          };
+
+          self.pop();
+          self.pop();
+          self.push(value);
+        },
+        OpCode::GetSuper => {
+          let name = self.read_string();
+          let superclass = self.pop();
+          let Some(superclass) = self.class_handle(superclass) else {
+            return Err(self.runtime_error("Superclass must be a class."));
+          };
+
+          self.bind_method(superclass, name)?;
+        },
+
+        // operators
+        OpCode::Equal => {
+          let b = self.pop();
+          let a = self.pop();
+          self.push(Value::Boolean(a == b));
+        },
+        OpCode::Greater => {
+          let (a, b) = self.pop_numbers()?;
+          self.push(Value::Boolean(a > b));
+        },
+        OpCode::Less => {
+          let (a, b) = self.pop_numbers()?;
+          self.push(Value::Boolean(a < b));
+        },
+        OpCode::Add => self.add()?,
+        OpCode::Subtract => {
+          let (a, b) = self.pop_numbers()?;
+          self.push(Value::Number(a - b));
+        },
+        OpCode::Multiply => {
+          let (a, b) = self.pop_numbers()?;
+          self.push(Value::Number(a * b));
+        },
+        OpCode::Divide => {
+          let (a, b) = self.pop_numbers()?;
+          self.push(Value::Number(a / b));
+        },
+        OpCode::Not => {
+          let value = self.pop();
+          self.push(Value::Boolean(!value.is_truthy()));
+        },
+        OpCode::Negate => {
+          let Value::Number(n) = self.peek(0) else {
+            return Err(self.runtime_error("unary minus only applicable to numbers"));
+          };
+
+          self.pop();
+          self.push(Value::Number(-n));
+        },
+        OpCode::Print => {
+          let value = self.pop();
+          println!("{}", self.heap.format(value));
+        },
+
+        // control flow
+        OpCode::Jump => {
+          let offset = self.read_u16() as usize;
+          self.frame_mut().ip += offset;
+        },
+        OpCode::JumpIfFalse => {
+          let offset = self.read_u16() as usize;
+
+          if !self.peek(0).is_truthy() {
+            self.frame_mut().ip += offset;
+          }
+        },
+        OpCode::Loop => {
+          let offset = self.read_u16() as usize;
+          self.frame_mut().ip -= offset;
+        },
+
+        // functions
+        OpCode::Call => {
+          let argc = self.read_byte() as usize;
+          self.call_value(self.peek(argc), argc)?;
+        },
+        OpCode::Closure => {
+          let Value::Obj(function) = self.read_constant() else {
+            unreachable!("closure constant is always a function");
+          };
+
+          let count = self.heap.function(function).proto.upvalue_count;
+          let mut upvalues = Vec::with_capacity(count);
+
+          for _ in 0..count {
+            let is_local = self.read_byte() == 1;
+            let index = self.read_byte() as usize;
+
+            let upvalue = if is_local {
+              self.capture_upvalue(self.frame().slots + index)
+            } else {
+              self.heap.closure(self.frame().closure).upvalues[index]
+            };
+
+            upvalues.push(upvalue);
+          }
+
+          let closure = self.heap.alloc(Obj::Closure(ObjClosure { function, upvalues }));
+          self.push(Value::Obj(closure));
+        },
+        OpCode::CloseUpvalue => {
+          self.close_upvalues(self.stack.len() - 1);
+          self.pop();
+        },
+        OpCode::Return => {
+          let result = self.pop();
+          let frame = self.frames.pop().expect("returned with no frame");
+          self.close_upvalues(frame.slots);
+
+          if self.frames.is_empty() {
+            self.stack.clear();
+            return Ok(());
+          }
+
+          self.stack.truncate(frame.slots);
+          self.push(result);
+        },
+
+        // classes
+        OpCode::Class => {
+          let name = self.read_string();
+          let class = self.heap.alloc(Obj::Class(ObjClass {
+            name: self.heap.string(name).to_string(),
+            methods: HashMap::new(),
+          }));
+
+          self.push(Value::Obj(class));
+        },
+        OpCode::Inherit => {
+          let Some(superclass) = self.class_handle(self.peek(1)) else {
+            return Err(self.runtime_error("Superclass must be a class."));
+          };
+
+          let Some(subclass) = self.class_handle(self.peek(0)) else {
+            return Err(self.runtime_error("Only classes can inherit."));
+          };
+
+          // copy down the inherited methods, so lookup never has to walk
+          let methods = self.heap.class(superclass).methods.clone();
+          self.heap.class_mut(subclass).methods.extend(methods);
+          self.pop();
+        },
+        OpCode::Method => {
+          let name = self.read_string();
+          let (Some(class), Some(method)) =
+            (self.class_handle(self.peek(1)), self.closure_handle(self.peek(0)))
+          else {
+            let msg = "Only closures can be methods, and only on classes.";
+            return Err(self.runtime_error(msg));
+          };
+
+          self.heap.class_mut(class).methods.insert(name, method);
+          self.pop();
+        },
+      }
+    }
+  }
+
+  // calls
+  fn call_value(&mut self, callee: Value, argc: usize) -> Result<()> {
+    let Value::Obj(handle) = callee else {
+      return Err(self.runtime_error("can only call functions and classes"));
+    };
+
+    let callee_slot = self.stack.len() - argc - 1;
+
+    match self.heap.get(handle) {
+      Obj::Closure(_) => self.call(handle, argc),
+      Obj::BoundMethod(bound) => {
+        let method = bound.method;
+        self.stack[callee_slot] = bound.receiver;
+        self.call(method, argc)
+      },
+      Obj::Class(class) => {
+        let init = class.methods.get(&self.init_string).copied();
+        let instance = self.heap.alloc(Obj::Instance(ObjInstance {
+          class: handle,
+          fields: HashMap::new(),
+        }));
+
+        self.stack[callee_slot] = Value::Obj(instance);
+
+        match init {
+          Some(init) => self.call(init, argc),
+          None if argc != 0 => Err(self.arity_error(0, argc)),
+          None => Ok(()),
+        }
+      },
+      Obj::Native(native) => {
+        if argc != native.arity {
+          return Err(self.arity_error(native.arity, argc));
+        }
+
+        let result = (native.func)(&self.stack[callee_slot + 1..]);
+        self.stack.truncate(callee_slot);
+        self.push(result);
+        Ok(())
+      },
+      _ => Err(self.runtime_error("can only call functions and classes")),
+    }
+  }
+
+  fn call(&mut self, closure: ObjRef, argc: usize) -> Result<()> {
+    let function = self.heap.function(self.heap.closure(closure).function);
+
+    if argc != function.proto.arity {
+      return Err(self.arity_error(function.proto.arity, argc));
+    }
+
+    if self.frames.len() == FRAMES_MAX {
+      return Err(self.runtime_error("Stack overflow."));
+    }
+
+    let frame = CallFrame {
+      closure,
+      proto: Rc::clone(&function.proto),
+      constants: Rc::clone(&function.constants),
+      ip: 0,
+      slots: self.stack.len() - argc - 1,
+    };
+
+    self.frames.push(frame);
+    Ok(())
+  }
+
+  // replaces the instance on top of the stack with the named method, bound
+  fn bind_method(&mut self, class: ObjRef, name: ObjRef) -> Result<()> {
+    let Some(&method) = self.heap.class(class).methods.get(&name) else {
+      let msg = format!("Undefined property '{}'.", self.heap.string(name));
+      return Err(self.runtime_error(msg));
+    };
+
+    let bound = self.heap.alloc(Obj::BoundMethod(ObjBoundMethod {
+      receiver: self.peek(0),
+      method,
+    }));
+
+    self.pop();
+    self.push(Value::Obj(bound));
+    Ok(())
+  }
+
+  // gc
+  fn collect_garbage(&mut self) {
+    let (objects_before, bytes_before) = (self.heap.live_objects(), self.heap.bytes_allocated());
+
+    for &value in &self.stack {
+      self.heap.mark_value(value);
+    }
+
+    for frame in &self.frames {
+      self.heap.mark_object(frame.closure);
+    }
+
+    for &upvalue in &self.open_upvalues {
+      self.heap.mark_object(upvalue);
+    }
+
+    for (&name, &value) in &self.globals {
+      self.heap.mark_object(name);
+      self.heap.mark_value(value);
+    }
+
+    self.heap.mark_object(self.init_string);
+
+    self.heap.trace();
+    self.heap.sweep();
+
+    if self.heap.config().log {
+      eprintln!(
+        "-- gc: freed {} objects, {} -> {} bytes, next at {}",
+        objects_before - self.heap.live_objects(),
+        bytes_before,
+        self.heap.bytes_allocated(),
+        self.heap.next_gc(),
+      );
+    }
+  }
+
+  // upvalues
+  fn capture_upvalue(&mut self, slot: usize) -> ObjRef {
+    let existing = self.open_upvalues.iter().find(|&&handle| {
+      matches!(self.heap.get(handle), Obj::Upvalue(ObjUpvalue::Open(s)) if *s == slot)
+    });
+
+    if let Some(&existing) = existing {
+      return existing;
+    }
+
+    let upvalue = self.heap.alloc(Obj::Upvalue(ObjUpvalue::Open(slot)));
+    self.open_upvalues.push(upvalue);
+    upvalue
+  }
+
+  // closes every open upvalue pointing at or above the given stack slot
+  fn close_upvalues(&mut self, last: usize) {
+    let Vm {
+      heap,
+      stack,
+      open_upvalues,
+      ..
+    } = self;
+
+    open_upvalues.retain(|&handle| {
+      let upvalue = heap.upvalue_mut(handle);
+
+      match *upvalue {
+        ObjUpvalue::Open(slot) if slot >= last => {
+          *upvalue = ObjUpvalue::Closed(stack[slot]);
+          false
+        },
+        _ => true,
+      }
+    });
+  }
+
+  // operators
+  fn add(&mut self) -> Result<()> {
+    let (left, right) = (self.peek(1), self.peek(0));
+
+    match (left, right) {
+      (Value::Number(a), Value::Number(b)) => {
+        self.pop();
+        self.pop();
+        self.push(Value::Number(a + b));
+      },
+      (Value::Obj(a), Value::Obj(b))
+        if self.heap.is_string(left) && self.heap.is_string(right) =>
+      {
+        let joined = format!("{}{}", self.heap.string(a), self.heap.string(b));
+        let result = self.heap.intern(&joined);
+        self.pop();
+        self.pop();
+        self.push(Value::Obj(result));
+      },
+      _ => return Err(self.runtime_error("+ needs either strings or numbers")),
+    }
+
+    Ok(())
+  }
+
+  fn pop_numbers(&mut self) -> Result<(f64, f64)> {
+    match (self.peek(1), self.peek(0)) {
+      (Value::Number(a), Value::Number(b)) => {
+        self.pop();
+        self.pop();
+        Ok((a, b))
+      },
+      _ => Err(self.runtime_error("operands must be two numbers")),
+    }
+  }
+
+  // helpers
+  fn load_function(&mut self, proto: &Rc<Function>) -> ObjRef {
+    let mut constants = Vec::with_capacity(proto.chunk.constants.len());
+
+    for constant in &proto.chunk.constants {
+      let value = match constant {
+        Constant::Number(n) => Value::Number(*n),
+        Constant::String(s) => Value::Obj(self.heap.intern(s)),
+        Constant::Function(f) => Value::Obj(self.load_function(f)),
+      };
+
+      constants.push(value);
+    }
+
+    self.heap.alloc(Obj::Function(ObjFunction {
+      proto: Rc::clone(proto),
+      constants: constants.into(),
+    }))
+  }
+
+  fn define_native(&mut self, name: &str, arity: usize, func: NativeFn) {
+    let name_ref = self.heap.intern(name);
+    let native = self.heap.alloc(Obj::Native(ObjNative {
+      name: name.to_string(),
+      arity,
+      func,
+    }));
+
+    self.globals.insert(name_ref, Value::Obj(native));
+  }
+
+  fn as_instance(&self, value: Value) -> Option<&ObjInstance> {
+    match value {
+      Value::Obj(handle) => match self.heap.get(handle) {
+        Obj::Instance(instance) => Some(instance),
+        _ => None,
+      },
+      _ => None,
+    }
+  }
+
+  // The compiler only ever builds classes out of classes and closures, but
+  // code loaded from a file could have anything on the stack, so these get
+  // checked rather than assumed.
+  fn class_handle(&self, value: Value) -> Option<ObjRef> {
+    match value {
+      Value::Obj(handle) if matches!(self.heap.get(handle), Obj::Class(_)) => {
+        Some(handle)
+      },
+      _ => None,
+    }
+  }
+
+  fn closure_handle(&self, value: Value) -> Option<ObjRef> {
+    match value {
+      Value::Obj(handle) if matches!(self.heap.get(handle), Obj::Closure(_)) => {
+        Some(handle)
+      },
+      _ => None,
+    }
+  }
+
+  fn frame(&self) -> &CallFrame {
+    self.frames.last().expect("no call frame")
+  }
+
+  fn frame_mut(&mut self) -> &mut CallFrame {
+    self.frames.last_mut().expect("no call frame")
+  }
+
+  fn read_byte(&mut self) -> u8 {
+    let frame = self.frame_mut();
+    let byte = frame.proto.chunk.code[frame.ip];
+    frame.ip += 1;
+    byte
+  }
+
+  fn read_u16(&mut self) -> u16 {
+    let frame = self.frame_mut();
+    let val = frame.proto.chunk.read_u16(frame.ip);
+    frame.ip += 2;
+    val
+  }
+
+  fn read_constant(&mut self) -> Value {
+    let idx = self.read_u16() as usize;
+    self.frame().constants[idx]
+  }
+
+  fn read_string(&mut self) -> ObjRef {
+    match self.read_constant() {
+      Value::Obj(handle) => handle,
+      other => unreachable!("expected a string constant, got {other:?}"),
+    }
+  }
+
+  fn push(&mut self, value: Value) {
+    self.stack.push(value);
+  }
+
+  fn pop(&mut self) -> Value {
+    self.stack.pop().expect("popped an empty stack")
+  }
+
+  fn peek(&self, distance: usize) -> Value {
+    self.stack[self.stack.len() - 1 - distance]
+  }
+
+  // errors
+  fn undefined_variable(&mut self, name: ObjRef) -> Error {
+    let msg = format!("undefined variable '{}'.", self.heap.string(name));
+    self.runtime_error(msg)
+  }
+
+  fn arity_error(&mut self, arity: usize, argc: usize) -> Error {
+    self.runtime_error(format!("Expected {arity} arguments but got {argc}."))
+  }
+
+  // Builds the error for the current instruction, and resets the VM so that
+  // it can be used again.
+  fn runtime_error(&mut self, msg: impl Into<String>) -> Error {
+    let line = self
+      .frames
+      .last()
+      .map_or(0, |frame| frame.proto.chunk.line_at(frame.ip.saturating_sub(1)));
+
+    self.stack.clear();
+    self.frames.clear();
+    self.open_upvalues.clear();
+
+    Error::VmRuntime(line, msg.into())
+  }
+}
+
+fn clock(_args: &[Value]) -> Value {
+  use std::time::SystemTime;
+
+  match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+    Ok(n) => Value::Number(n.as_secs() as f64),
+    Err(_) => panic!("SystemTime before UNIX EPOCH!"),
+  }
+}
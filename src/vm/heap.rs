@@ -0,0 +1,243 @@
+use std::collections::HashMap;
+
+use crate::gc::GcConfig;
+use crate::vm::object::*;
+use crate::vm::value::Value;
+
+// A handle to an object in the heap. These are just indexes, so they're
+// cheap to copy around and compare. Once an object is collected its slot gets
+// reused, so a handle is only good for as long as the object is reachable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObjRef(usize);
+
+#[derive(Debug)]
+struct Slot {
+  obj: Obj,
+  marked: bool,
+}
+
+// All of the VM's objects live in here, and everything else refers to them
+// by handle. Collection is plain mark and sweep: the VM marks its roots, then
+// trace() follows everything reachable from them, and sweep() frees the rest.
+#[derive(Debug, Default)]
+pub struct Heap {
+  objects: Vec<Option<Slot>>,
+  free: Vec<usize>,
+  // Interned strings. This table doesn't keep anything alive: strings that
+  // aren't otherwise reachable are dropped from it when they're swept.
+  strings: HashMap<String, ObjRef>,
+  gray: Vec<ObjRef>,
+  config: GcConfig,
+  bytes_allocated: usize,
+  next_gc: usize,
+  allocations_since_gc: usize,
+}
+
+impl Heap {
+  pub fn with_config(config: GcConfig) -> Self {
+    Heap {
+      config,
+      next_gc: config.initial_threshold,
+      ..Heap::default()
+    }
+  }
+
+  pub fn alloc(&mut self, obj: Obj) -> ObjRef {
+    self.bytes_allocated += obj.size();
+    self.allocations_since_gc += 1;
+
+    let slot = Some(Slot { obj, marked: false });
+
+    match self.free.pop() {
+      Some(idx) => {
+        self.objects[idx] = slot;
+        ObjRef(idx)
+      },
+      None => {
+        self.objects.push(slot);
+        ObjRef(self.objects.len() - 1)
+      },
+    }
+  }
+
+  // Returns the one and only string object with these contents.
+  pub fn intern(&mut self, s: &str) -> ObjRef {
+    if let Some(&existing) = self.strings.get(s) {
+      return existing;
+    }
+
+    let handle = self.alloc(Obj::String(s.to_string()));
+    self.strings.insert(s.to_string(), handle);
+    handle
+  }
+
+  pub fn get(&self, handle: ObjRef) -> &Obj {
+    match &self.objects[handle.0] {
+      Some(slot) => &slot.obj,
+      None => panic!("use of collected object {handle:?}"),
+    }
+  }
+
+  pub fn get_mut(&mut self, handle: ObjRef) -> &mut Obj {
+    match &mut self.objects[handle.0] {
+      Some(slot) => &mut slot.obj,
+      None => panic!("use of collected object {handle:?}"),
+    }
+  }
+
+  // gc
+  pub fn config(&self) -> &GcConfig {
+    &self.config
+  }
+
+  pub fn should_collect(&self) -> bool {
+    if self.config.stress {
+      self.allocations_since_gc > 0
+    } else {
+      self.bytes_allocated > self.next_gc
+    }
+  }
+
+  pub fn live_objects(&self) -> usize {
+    self.objects.len() - self.free.len()
+  }
+
+  pub fn bytes_allocated(&self) -> usize {
+    self.bytes_allocated
+  }
+
+  pub fn next_gc(&self) -> usize {
+    self.next_gc
+  }
+
+  pub fn mark_value(&mut self, value: Value) {
+    if let Value::Obj(handle) = value {
+      self.mark_object(handle);
+    }
+  }
+
+  pub fn mark_object(&mut self, handle: ObjRef) {
+    let slot = self.objects[handle.0]
+      .as_mut()
+      .expect("marked a collected object");
+
+    if !slot.marked {
+      slot.marked = true;
+      self.gray.push(handle);
+    }
+  }
+
+  // Marks everything reachable from whatever has been marked so far.
+  pub fn trace(&mut self) {
+    let mut children = vec![];
+
+    while let Some(handle) = self.gray.pop() {
+      self.get(handle).children(&mut children);
+
+      for child in children.drain(..) {
+        self.mark_value(child);
+      }
+    }
+  }
+
+  // Frees everything that didn't get marked, and unmarks everything that did
+  // so it's ready for next time.
+  pub fn sweep(&mut self) {
+    let objects = &self.objects;
+    self.strings.retain(|_, handle| {
+      objects[handle.0].as_ref().is_some_and(|slot| slot.marked)
+    });
+
+    let mut live_bytes = 0;
+
+    for (idx, entry) in self.objects.iter_mut().enumerate() {
+      match entry {
+        Some(slot) if slot.marked => {
+          slot.marked = false;
+          live_bytes += slot.obj.size();
+        },
+        Some(_) => {
+          *entry = None;
+          self.free.push(idx);
+        },
+        None => (),
+      }
+    }
+
+    self.bytes_allocated = live_bytes;
+    self.next_gc = (live_bytes * self.config.grow_factor).max(self.config.initial_threshold);
+    self.allocations_since_gc = 0;
+  }
+
+  // Typed accessors. The compiler only ever emits code that uses the right
+  // kind of object in the right place, so a mismatch here is a bug in the VM.
+  pub fn string(&self, handle: ObjRef) -> &str {
+    match self.get(handle) {
+      Obj::String(s) => s,
+      other => panic!("expected a string, got {other:?}"),
+    }
+  }
+
+  pub fn function(&self, handle: ObjRef) -> &ObjFunction {
+    match self.get(handle) {
+      Obj::Function(f) => f,
+      other => panic!("expected a function, got {other:?}"),
+    }
+  }
+
+  pub fn closure(&self, handle: ObjRef) -> &ObjClosure {
+    match self.get(handle) {
+      Obj::Closure(c) => c,
+      other => panic!("expected a closure, got {other:?}"),
+    }
+  }
+
+  pub fn class(&self, handle: ObjRef) -> &ObjClass {
+    match self.get(handle) {
+      Obj::Class(c) => c,
+      other => panic!("expected a class, got {other:?}"),
+    }
+  }
+
+  pub fn class_mut(&mut self, handle: ObjRef) -> &mut ObjClass {
+    match self.get_mut(handle) {
+      Obj::Class(c) => c,
+      other => panic!("expected a class, got {other:?}"),
+    }
+  }
+
+  pub fn upvalue_mut(&mut self, handle: ObjRef) -> &mut ObjUpvalue {
+    match self.get_mut(handle) {
+      Obj::Upvalue(u) => u,
+      other => panic!("expected an upvalue, got {other:?}"),
+    }
+  }
+
+  pub fn is_string(&self, value: Value) -> bool {
+    matches!(value, Value::Obj(handle) if matches!(self.get(handle), Obj::String(_)))
+  }
+
+  // This matches the tree-walker's Display for LoxValue, so that the two
+  // backends print the same things.
+  pub fn format(&self, value: Value) -> String {
+    match value {
+      Value::Nil => "nil".into(),
+      Value::Boolean(b) => b.to_string(),
+      Value::Number(n) => n.to_string(),
+      Value::Obj(handle) => self.format_obj(handle),
+    }
+  }
+
+  fn format_obj(&self, handle: ObjRef) -> String {
+    match self.get(handle) {
+      Obj::String(s) => s.clone(),
+      Obj::Function(f) => f.proto.display_name(),
+      Obj::Native(n) => format!("<function {}>", n.name),
+      Obj::Closure(c) => self.format_obj(c.function),
+      Obj::Upvalue(_) => "<upvalue>".into(),
+      Obj::Class(c) => format!("<class {}>", c.name),
+      Obj::Instance(i) => format!("<{} instance>", self.class(i.class).name),
+      Obj::BoundMethod(b) => self.format_obj(b.method),
+    }
+  }
+}
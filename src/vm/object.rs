@@ -0,0 +1,122 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::chunk::Function;
+use crate::vm::heap::ObjRef;
+use crate::vm::value::Value;
+
+pub type NativeFn = fn(&[Value]) -> Value;
+
+#[derive(Debug)]
+pub enum Obj {
+  String(String),
+  Function(ObjFunction),
+  Native(ObjNative),
+  Closure(ObjClosure),
+  Upvalue(ObjUpvalue),
+  Class(ObjClass),
+  Instance(ObjInstance),
+  BoundMethod(ObjBoundMethod),
+}
+
+// A compiled function, loaded into the heap: the code stays shared with the
+// compiler's output, but the constants are turned into real values.
+#[derive(Debug)]
+pub struct ObjFunction {
+  pub proto: Rc<Function>,
+  pub constants: Rc<[Value]>,
+}
+
+pub struct ObjNative {
+  pub name: String,
+  pub arity: usize,
+  pub func: NativeFn,
+}
+
+#[derive(Debug)]
+pub struct ObjClosure {
+  pub function: ObjRef,
+  pub upvalues: Vec<ObjRef>,
+}
+
+// An upvalue is open while the variable it refers to is still on the stack,
+// and closed (holding the value itself) once it's gone.
+#[derive(Debug)]
+pub enum ObjUpvalue {
+  Open(usize),
+  Closed(Value),
+}
+
+#[derive(Debug)]
+pub struct ObjClass {
+  pub name: String,
+  pub methods: HashMap<ObjRef, ObjRef>,
+}
+
+#[derive(Debug)]
+pub struct ObjInstance {
+  pub class: ObjRef,
+  pub fields: HashMap<ObjRef, Value>,
+}
+
+#[derive(Debug)]
+pub struct ObjBoundMethod {
+  pub receiver: Value,
+  pub method: ObjRef,
+}
+
+impl Obj {
+  // Roughly how much memory this object is holding on to. It only needs to be
+  // good enough to decide when to collect.
+  pub fn size(&self) -> usize {
+    let payload = match self {
+      Obj::String(s) => s.capacity(),
+      Obj::Function(f) => f.constants.len() * size_of::<Value>(),
+      Obj::Native(n) => n.name.capacity(),
+      Obj::Closure(c) => c.upvalues.capacity() * size_of::<ObjRef>(),
+      Obj::Upvalue(_) | Obj::BoundMethod(_) => 0,
+      Obj::Class(c) => c.name.capacity() + c.methods.capacity() * size_of::<(ObjRef, ObjRef)>(),
+      Obj::Instance(i) => i.fields.capacity() * size_of::<(ObjRef, Value)>(),
+    };
+
+    size_of::<Obj>() + payload
+  }
+
+  // Everything this object refers to, for the collector to trace through.
+  pub fn children(&self, out: &mut Vec<Value>) {
+    match self {
+      Obj::String(_) | Obj::Native(_) => (),
+      Obj::Function(f) => out.extend_from_slice(&f.constants),
+      Obj::Closure(c) => {
+        out.push(Value::Obj(c.function));
+        out.extend(c.upvalues.iter().map(|&u| Value::Obj(u)));
+      },
+      // open upvalues point into the stack, which is a root anyway
+      Obj::Upvalue(ObjUpvalue::Open(_)) => (),
+      Obj::Upvalue(ObjUpvalue::Closed(value)) => out.push(*value),
+      Obj::Class(c) => {
+        for (&name, &method) in &c.methods {
+          out.push(Value::Obj(name));
+          out.push(Value::Obj(method));
+        }
+      },
+      Obj::Instance(i) => {
+        out.push(Value::Obj(i.class));
+        for (&name, &value) in &i.fields {
+          out.push(Value::Obj(name));
+          out.push(value);
+        }
+      },
+      Obj::BoundMethod(b) => {
+        out.push(b.receiver);
+        out.push(Value::Obj(b.method));
+      },
+    }
+  }
+}
+
+impl std::fmt::Debug for ObjNative {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "<function {}>", self.name)
+  }
+}
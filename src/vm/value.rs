@@ -0,0 +1,22 @@
+use crate::vm::heap::ObjRef;
+
+// Values on the VM's stack. Anything bigger than a number lives in the heap,
+// and we just carry around a handle to it. Strings are interned, so two
+// string values are equal exactly when their handles are.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+  Nil,
+  Boolean(bool),
+  Number(f64),
+  Obj(ObjRef),
+}
+
+impl Value {
+  pub fn is_truthy(&self) -> bool {
+    match self {
+      Value::Nil => false,
+      Value::Boolean(b) => *b,
+      _ => true,
+    }
+  }
+}
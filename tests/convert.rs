@@ -0,0 +1,33 @@
+use lox::value::{FromLoxValue, LoxValue};
+
+fn num<T: FromLoxValue>(n: f64) -> lox::Result<T> {
+  T::from_lox_value(LoxValue::Number(n))
+}
+
+#[test]
+fn integer_bounds_are_exclusive_at_the_top() {
+  let two_63 = 2f64.powi(63);
+  let two_64 = 2f64.powi(64);
+
+  assert!(num::<i64>(two_63).is_err());
+  assert!(num::<i64>(-two_63).is_ok());
+  assert!(num::<i64>(-two_63 - 4096.0).is_err());
+  assert!(num::<u64>(two_64).is_err());
+  assert!(num::<u64>(two_64 - 4096.0).is_ok());
+  assert!(num::<u64>(-1.0).is_err());
+
+  assert_eq!(num::<i8>(127.0).unwrap(), 127);
+  assert!(num::<i8>(128.0).is_err());
+  assert_eq!(num::<i8>(-128.0).unwrap(), -128);
+  assert!(num::<i8>(-129.0).is_err());
+  assert_eq!(num::<u8>(255.0).unwrap(), 255);
+  assert!(num::<u8>(256.0).is_err());
+  assert_eq!(num::<u32>(4294967295.0).unwrap(), u32::MAX);
+  assert!(num::<u32>(4294967296.0).is_err());
+}
+
+#[test]
+fn fractions_and_other_types_are_rejected() {
+  assert!(num::<i32>(1.5).is_err());
+  assert!(i32::from_lox_value(LoxValue::Nil).is_err());
+}
@@ -0,0 +1,71 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// Every script in tests/corpus says what it should print, one line at a time,
+// in `// expect:` comments. Both backends have to print exactly that, with or
+// without their collectors running all the time.
+
+fn scripts() -> Vec<PathBuf> {
+  let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/corpus");
+  let mut scripts: Vec<PathBuf> = fs::read_dir(dir)
+    .unwrap()
+    .map(|entry| entry.unwrap().path())
+    .filter(|path| path.extension().is_some_and(|ext| ext == "lox"))
+    .collect();
+
+  scripts.sort();
+  assert!(!scripts.is_empty());
+  scripts
+}
+
+fn expected(script: &Path) -> String {
+  fs::read_to_string(script)
+    .unwrap()
+    .lines()
+    .filter_map(|line| line.split_once("// expect: "))
+    .map(|(_, expect)| format!("{expect}\n"))
+    .collect()
+}
+
+fn run_corpus(flags: &[&str]) {
+  for script in scripts() {
+    let output = Command::new(env!("CARGO_BIN_EXE_lox"))
+      .args(flags)
+      .arg(&script)
+      .output()
+      .unwrap();
+
+    let name = script.display();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+      output.status.success(),
+      "{name} {flags:?} failed:\n{stderr}"
+    );
+    assert_eq!(
+      String::from_utf8_lossy(&output.stdout),
+      expected(&script),
+      "{name} {flags:?}"
+    );
+  }
+}
+
+#[test]
+fn tree_walker() {
+  run_corpus(&["--backend=tree"]);
+}
+
+#[test]
+fn vm() {
+  run_corpus(&["--backend=vm"]);
+}
+
+#[test]
+fn tree_walker_under_gc_stress() {
+  run_corpus(&["--backend=tree", "--gc-stress"]);
+}
+
+#[test]
+fn vm_under_gc_stress() {
+  run_corpus(&["--backend=vm", "--gc-stress"]);
+}
@@ -0,0 +1,56 @@
+use lox::diagnostics::render;
+use lox::{Error, Interpreter};
+
+fn render_error(source: &str) -> String {
+  let mut lox = Interpreter::with_output(std::io::sink());
+  let err = lox.eval(source).unwrap_err();
+  render(&err, "test.lox", source, false)
+}
+
+#[test]
+fn carets_cover_the_whole_token() {
+  let source = "var x = 1;\nprint x + missing;";
+
+  assert_eq!(
+    render_error(source),
+    "\
+error: undefined variable 'missing'.
+ --> test.lox:2:11
+  |
+2 | print x + missing;
+  |           ^^^^^^^"
+  );
+}
+
+#[test]
+fn tabs_before_the_span_are_kept_so_the_carets_line_up() {
+  let source = "{\n\t\tprint nope;\n}";
+  let rendered = render_error(source);
+
+  assert!(rendered.contains("\n2 | \t\tprint nope;\n"), "{rendered}");
+  assert!(rendered.ends_with("\n  | \t\t      ^^^^"), "{rendered}");
+}
+
+#[test]
+fn every_error_is_rendered_without_color_when_asked() {
+  let rendered = render_error("var a = ;\nvar b = ;");
+
+  assert!(!rendered.contains('\x1b'), "{rendered}");
+  assert_eq!(rendered.matches("error: ").count(), 2, "{rendered}");
+  assert!(rendered.contains("--> test.lox:1:9"), "{rendered}");
+  assert!(rendered.contains("--> test.lox:2:9"), "{rendered}");
+
+  let mut lox = Interpreter::with_output(std::io::sink());
+  let err = lox.eval("print -nil;").unwrap_err();
+  assert!(render(&err, "test.lox", "print -nil;", true).contains('\x1b'));
+}
+
+#[test]
+fn errors_with_only_a_line_get_no_underline() {
+  let err = Error::VmRuntime(2, "Stack overflow.".into());
+
+  assert_eq!(
+    render(&err, "test.lox", "fun f() {}\nf();", false),
+    "error: Stack overflow.\n --> test.lox:2\n  |\n2 | f();"
+  );
+}
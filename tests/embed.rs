@@ -0,0 +1,252 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use lox::symbol::Symbol;
+use lox::value::{LoxValue, Param, ParamType, Signature};
+use lox::{Error, Interpreter};
+
+#[test]
+fn output_fn_gets_whole_lines() {
+  let lines = Rc::new(RefCell::new(vec![]));
+  let sink = Rc::clone(&lines);
+  let mut lox =
+    Interpreter::with_output_fn(move |s| sink.borrow_mut().push(s.to_owned()));
+
+  lox.eval("class B {} print B(); print 1;").unwrap();
+
+  assert_eq!(*lines.borrow(), ["<B instance>\n", "1\n"]);
+}
+
+#[test]
+fn eval_returns_a_trailing_expression() {
+  let mut lox = Interpreter::with_output(std::io::sink());
+
+  assert_eq!(lox.eval("1 + 2").unwrap().to_string(), "3");
+  assert_eq!(lox.eval("var x = 4; print 1; x").unwrap().to_string(), "4");
+  assert_eq!(lox.eval("x;").unwrap().to_string(), "4");
+  assert_eq!(lox.eval("x = 5;").unwrap().to_string(), "5");
+  assert_eq!(lox.eval("print x;").unwrap().to_string(), "nil");
+  assert!(lox.eval("print 1").is_err());
+  assert!(lox.eval("{ x }").is_err());
+}
+
+#[test]
+fn get_global_does_not_intern_the_name() {
+  let lox = Interpreter::with_output(std::io::sink());
+
+  assert!(lox.get_global("no_such_global").is_none());
+  assert!(Symbol::lookup("no_such_global").is_none());
+}
+
+#[test]
+fn runaway_recursion_is_a_stack_overflow() {
+  let mut lox = Interpreter::with_output(std::io::sink());
+  lox.set_max_call_depth(20);
+  lox.eval("fun d(n) { if (n == 0) return 0; return d(n - 1); }").unwrap();
+
+  assert_eq!(lox.eval("d(19)").unwrap().to_string(), "0");
+  assert!(matches!(lox.eval("d(1000)"), Err(Error::StackOverflow(_))));
+
+  // and it's all unwound afterwards, so the next call starts from the top
+  assert_eq!(lox.eval("d(19)").unwrap().to_string(), "0");
+}
+
+#[test]
+fn runtime_errors_in_calls_carry_a_trace() {
+  let mut lox = Interpreter::with_output(std::io::sink());
+  let source =
+    "fun inner() {\n  return 1 + nil;\n}\nfun outer() {\n  inner();\n}\nouter();";
+
+  let Err(Error::Traced(err, trace)) = lox.eval(source) else {
+    panic!("expected a traced error");
+  };
+
+  assert!(matches!(*err, Error::Runtime(..)));
+  assert_eq!(
+    trace,
+    ["[line 2] in inner()", "[line 5] in outer()", "[line 7] in script"]
+  );
+}
+
+#[test]
+fn step_budget_stops_runaway_loops() {
+  let mut lox = Interpreter::with_output(std::io::sink());
+  lox.set_step_budget(Some(100));
+
+  assert!(matches!(lox.eval("while (true) {}"), Err(Error::OutOfSteps(_))));
+  assert_eq!(lox.steps_left(), Some(0));
+
+  lox.set_step_budget(Some(100));
+  lox.eval("for (var i = 0; i < 10; i = i + 1) {}").unwrap();
+  assert!(lox.steps_left().is_some_and(|steps| steps > 0 && steps < 100));
+}
+
+#[test]
+fn setting_the_interrupt_flag_stops_the_script() {
+  let mut lox = Interpreter::with_output(std::io::sink());
+  let flag = Arc::new(AtomicBool::new(false));
+  lox.set_interrupt_flag(Arc::clone(&flag));
+
+  lox.eval("for (var i = 0; i < 10; i = i + 1) {}").unwrap();
+
+  flag.store(true, Ordering::Relaxed);
+  assert!(matches!(lox.eval("while (true) {}"), Err(Error::Interrupted(_))));
+}
+
+#[test]
+fn the_default_limits_are_safe_on_a_small_thread() {
+  // the same stack spawned threads get by default, so it doesn't depend on
+  // RUST_MIN_STACK
+  let thread = std::thread::Builder::new().stack_size(2 * 1024 * 1024);
+
+  let result = thread
+    .spawn(|| {
+      let mut lox = Interpreter::with_output(std::io::sink());
+      let source = "
+        fun d(n) {
+          { var a = n; if (true) { while (true) { { return d(a + 1); } } } }
+        }
+        d(0)";
+
+      matches!(lox.eval(source), Err(Error::StackOverflow(_)))
+    })
+    .unwrap()
+    .join();
+
+  assert!(result.unwrap());
+}
+
+#[test]
+fn signatures_check_argument_types() {
+  let signature = Signature::new(vec![
+    Param::Required(ParamType::Number),
+    Param::Optional(ParamType::String),
+  ]);
+  let num = || LoxValue::Number(1.0);
+  let string = || LoxValue::String("s".into());
+
+  assert_eq!(signature.min_arity(), 1);
+  assert_eq!(signature.max_arity(), Some(2));
+  assert!(signature.check_types("f", &[num()]).is_ok());
+  assert!(signature.check_types("f", &[num(), LoxValue::Nil]).is_ok());
+  assert!(signature.check_types("f", &[num(), string()]).is_ok());
+  assert_eq!(
+    signature.check_types("f", &[string()]),
+    Err("f expects a number for argument 1, got string".to_string())
+  );
+  assert_eq!(
+    signature.check_types("f", &[num(), num()]),
+    Err("f expects a string for argument 2, got number".to_string())
+  );
+}
+
+#[test]
+fn natives_with_optional_parameters_can_be_called_without_them() {
+  let mut lox = Interpreter::with_output(std::io::sink());
+  let signature = Signature::new(vec![
+    Param::Required(ParamType::String),
+    Param::Optional(ParamType::String),
+  ]);
+  let greet = |_: &mut Interpreter, args: Vec<LoxValue>| {
+    let greeting = match args.get(1) {
+      Some(LoxValue::String(s)) => s.to_string(),
+      _ => "hello".to_string(),
+    };
+
+    Ok(LoxValue::String(format!("{greeting}, {}", args[0]).into()))
+  };
+  lox.define_global(
+    "greet",
+    LoxValue::new_native("greet".into(), signature, Box::new(greet)),
+  );
+
+  assert_eq!(lox.eval("greet(\"you\")").unwrap().to_string(), "hello, you");
+  assert_eq!(lox.eval("greet(\"you\", nil)").unwrap().to_string(), "hello, you");
+  assert_eq!(lox.eval("greet(\"you\", \"hi\")").unwrap().to_string(), "hi, you");
+
+  let err = lox.eval("greet(1)").unwrap_err().to_string();
+  assert!(err.contains("greet expects a string for argument 1"), "{err}");
+  let err = lox.eval("greet(\"you\", 2)").unwrap_err().to_string();
+  assert!(err.contains("greet expects a string for argument 2"), "{err}");
+}
+
+#[test]
+fn variadic_natives_take_a_range_of_arguments() {
+  let mut lox = Interpreter::with_output(std::io::sink());
+  let count = |_: &mut Interpreter, args: Vec<LoxValue>| {
+    Ok(LoxValue::Number(args.len() as f64))
+  };
+  lox.define_global(
+    "some",
+    LoxValue::new_variadic("some".into(), 1, Some(2), Box::new(count)),
+  );
+  lox.define_global(
+    "many",
+    LoxValue::new_variadic("many".into(), 1, None, Box::new(count)),
+  );
+
+  assert_eq!(lox.eval("some(1)").unwrap(), LoxValue::Number(1.0));
+  assert_eq!(lox.eval("some(1, 2)").unwrap(), LoxValue::Number(2.0));
+  assert_eq!(lox.eval("many(1, 2, 3, 4)").unwrap(), LoxValue::Number(4.0));
+
+  let err = lox.eval("some(1, 2, 3)").unwrap_err().to_string();
+  assert!(err.contains("Expected 1 to 2 arguments but got 3."), "{err}");
+  let err = lox.eval("some()").unwrap_err().to_string();
+  assert!(err.contains("Expected 1 to 2 arguments but got 0."), "{err}");
+  let err = lox.eval("many()").unwrap_err().to_string();
+  assert!(err.contains("Expected at least 1 argument but got 0."), "{err}");
+  let err = lox.eval("fun two(a, b) {} two(1)").unwrap_err().to_string();
+  assert!(err.contains("Expected 2 arguments but got 1."), "{err}");
+}
+
+#[test]
+fn registered_functions_and_globals_round_trip() {
+  let mut lox = Interpreter::with_output(std::io::sink());
+  lox.register_fn("scale", |n: f64, by: Option<f64>| Ok(n * by.unwrap_or(2.0)));
+  lox.register_fn("shout", |s: String| Ok(s.to_uppercase()));
+  lox.define_global("limit", 10.0);
+  lox.define_global("name", "lox");
+
+  lox
+    .eval("var big = scale(limit); var bigger = scale(limit, 3); var loud = shout(name);")
+    .unwrap();
+
+  assert_eq!(lox.get_global("big"), Some(LoxValue::Number(20.0)));
+  assert_eq!(lox.get_global("bigger"), Some(LoxValue::Number(30.0)));
+  assert_eq!(lox.get_global("loud").unwrap().to_string(), "LOX");
+  assert!(lox.get_global("quiet").is_none());
+
+  let err = lox.eval("shout(limit)").unwrap_err().to_string();
+  assert!(err.contains("shout expects a string for argument 1"), "{err}");
+}
+
+#[test]
+fn bad_arguments_to_registered_functions_are_all_reported_alike() {
+  let mut lox = Interpreter::with_output(std::io::sink());
+  lox.register_fn("small", |n: u8| Ok(n));
+
+  let message = |err| match err {
+    Error::Runtime(_, msg) => msg,
+    err => panic!("expected a plain runtime error, got {err:?}"),
+  };
+
+  let wrong_type = message(lox.eval("small(\"x\")").unwrap_err());
+  let out_of_range = message(lox.eval("small(300)").unwrap_err());
+  assert_eq!(wrong_type, "small expects a number for argument 1, got string");
+  assert_eq!(
+    out_of_range,
+    "small expects a whole number that fits in u8 for argument 1, got 300"
+  );
+
+  // and from inside a function, both get the same trace
+  lox.eval("fun f(n) { small(n); }").unwrap();
+  for call in ["f(\"x\")", "f(300)"] {
+    let Err(Error::Traced(_, trace)) = lox.eval(call) else {
+      panic!("expected a traced error from {call}");
+    };
+
+    assert_eq!(trace, ["[line 1] in f()", "[line 1] in script"]);
+  }
+}
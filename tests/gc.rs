@@ -0,0 +1,49 @@
+use std::rc::Rc;
+
+use lox::gc::GcConfig;
+use lox::value::LoxValue;
+use lox::Interpreter;
+
+fn stressed() -> Interpreter {
+  let mut lox = Interpreter::with_output(std::io::sink());
+  lox.set_gc_config(GcConfig {
+    stress: true,
+    ..GcConfig::default()
+  });
+  lox
+}
+
+#[test]
+fn instances_in_a_cycle_are_freed() {
+  let mut lox = stressed();
+  let source =
+    "class Node {} var a = Node(); var b = Node(); a.other = b; b.other = a; a";
+
+  let a = match lox.eval(source).unwrap() {
+    LoxValue::Instance(a) => Rc::downgrade(&a),
+    _ => panic!("expected an instance"),
+  };
+  lox
+    .eval("a = nil; b = nil; { var c = 1; } var d = 2;")
+    .unwrap();
+
+  assert!(a.upgrade().is_none());
+}
+
+#[test]
+fn values_held_from_rust_are_not_collected() {
+  let mut lox = stressed();
+  let source = "
+    fun make() {
+      var n = 0;
+      fun count() { n = n + 1; return n; }
+      return count;
+    }
+    make()";
+
+  let count = lox.eval(source).unwrap();
+  lox.eval("{ var c = 1; } var d = 2;").unwrap();
+  lox.define_global("count", count);
+
+  assert_eq!(lox.eval("count(); count()").unwrap(), LoxValue::Number(2.0));
+}
@@ -0,0 +1,85 @@
+use lox::token::{Span, TokenType};
+use lox::{Error, Interpreter, Scanner};
+
+fn kinds(source: &str) -> Vec<TokenType> {
+  let tokens = Scanner::new(source.to_string()).into_tokens();
+  tokens.into_iter().map(|token| token.kind).collect()
+}
+
+#[test]
+fn a_number_with_a_trailing_dot_is_a_number_then_a_dot() {
+  assert_eq!(
+    kinds("1."),
+    [TokenType::Number(1.0), TokenType::Dot, TokenType::EOF]
+  );
+  assert_eq!(
+    kinds("print 12."),
+    [
+      TokenType::Print,
+      TokenType::Number(12.0),
+      TokenType::Dot,
+      TokenType::EOF
+    ]
+  );
+
+  let mut lox = Interpreter::with_output(std::io::sink());
+  assert!(lox.eval("1.").is_err());
+}
+
+fn spans(source: &str) -> Vec<Span> {
+  let tokens = Scanner::new(source.to_string()).into_tokens();
+  tokens.into_iter().map(|token| token.span).collect()
+}
+
+#[test]
+fn spans_give_byte_offsets_and_character_columns() {
+  let source = "var x = 10;\n  print x;";
+
+  assert_eq!(
+    spans(source)[..6],
+    [
+      Span::new(0, 3, 1, 1),
+      Span::new(4, 5, 1, 5),
+      Span::new(6, 7, 1, 7),
+      Span::new(8, 10, 1, 9),
+      Span::new(10, 11, 1, 11),
+      Span::new(14, 19, 2, 3),
+    ]
+  );
+}
+
+#[test]
+fn multi_byte_characters_count_as_one_column_but_all_their_bytes() {
+  // "é" is two bytes and "🦀" is four
+  let source = "print \"é🦀\"; x";
+  let spans = spans(source);
+
+  assert_eq!(spans[1], Span::new(6, 14, 1, 7));
+  assert_eq!(&source[spans[1].start..spans[1].end], "\"é🦀\"");
+  assert_eq!(spans[2], Span::new(14, 15, 1, 11));
+  assert_eq!(spans[3], Span::new(16, 17, 1, 13));
+
+  // and the EOF token sits at the very end
+  assert_eq!(spans[4], Span::new(17, 17, 1, 14));
+}
+
+#[test]
+fn scan_and_parse_errors_all_come_back_in_source_order() {
+  let mut lox = Interpreter::with_output(std::io::sink());
+  let source = "var a = @;\nprint 1 +;\nvar b = \"x\" # 1;\nprint (;\nvar c = 1;";
+
+  let Err(Error::Invalid(errors)) = lox.eval(source) else {
+    panic!("expected every error at once");
+  };
+
+  let found: Vec<_> = errors
+    .iter()
+    .map(|err| match err {
+      Error::Scan(span, _) => ("scan", span.line),
+      Error::Parse(token, _) => ("parse", token.line()),
+      err => panic!("unexpected {err:?}"),
+    })
+    .collect();
+
+  assert_eq!(found, [("scan", 1), ("parse", 2), ("scan", 3), ("parse", 4)]);
+}
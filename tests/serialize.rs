@@ -0,0 +1,137 @@
+use std::fs;
+use std::rc::Rc;
+
+use lox::chunk::{Chunk, Constant, Function, OpCode};
+use lox::{serialize, Error, Vm};
+
+enum Byte {
+  Op(OpCode),
+  Arg(u8),
+}
+
+use Byte::{Arg, Op};
+
+fn script(code: &[Byte], constants: Vec<Constant>) -> Function {
+  let mut chunk = Chunk::new();
+  chunk.constants = constants;
+
+  for byte in code {
+    match byte {
+      Op(op) => chunk.write_op(*op, 1),
+      Arg(arg) => chunk.write(*arg, 1),
+    }
+  }
+
+  Function {
+    chunk,
+    ..Function::default()
+  }
+}
+
+// Writes the program out to a file and loads it back, the way `lox run` would.
+fn round_trip(name: &str, function: &Function) -> lox::Result<Rc<Function>> {
+  let path =
+    std::env::temp_dir().join(format!("lox-test-{}-{name}.loxc", std::process::id()));
+  fs::write(&path, serialize::write_program(function)).unwrap();
+
+  let bytes = fs::read(&path).unwrap();
+  fs::remove_file(&path).unwrap();
+
+  assert!(serialize::is_compiled(&bytes));
+  serialize::read_program(&bytes)
+}
+
+#[test]
+fn well_behaved_code_loads() {
+  let code = [
+    Op(OpCode::Constant),
+    Arg(0),
+    Arg(0),
+    Op(OpCode::GetLocal),
+    Arg(1),
+    Op(OpCode::Print),
+    Op(OpCode::Pop),
+    Op(OpCode::Nil),
+    Op(OpCode::Return),
+  ];
+
+  let function = script(&code, vec![Constant::Number(1.0)]);
+  assert!(round_trip("ok", &function).is_ok());
+}
+
+#[test]
+fn popping_an_empty_stack_is_rejected() {
+  let code = [Op(OpCode::Pop), Op(OpCode::Pop), Op(OpCode::Return)];
+
+  let result = round_trip("pop", &script(&code, vec![]));
+  assert!(matches!(result, Err(Error::Bytecode(_))));
+}
+
+#[test]
+fn locals_past_the_top_of_the_stack_are_rejected() {
+  let code = [
+    Op(OpCode::GetLocal),
+    Arg(200),
+    Op(OpCode::Print),
+    Op(OpCode::Nil),
+    Op(OpCode::Return),
+  ];
+
+  let result = round_trip("local", &script(&code, vec![]));
+  assert!(matches!(result, Err(Error::Bytecode(_))));
+}
+
+#[test]
+fn paths_that_disagree_about_the_stack_are_rejected() {
+  // pushes an extra value only when the condition is true
+  let code = [
+    Op(OpCode::True),
+    Op(OpCode::JumpIfFalse),
+    Arg(1),
+    Arg(0),
+    Op(OpCode::Nil),
+    Op(OpCode::Return),
+  ];
+
+  let result = round_trip("paths", &script(&code, vec![]));
+  assert!(matches!(result, Err(Error::Bytecode(_))));
+}
+
+#[test]
+fn scripts_with_upvalues_or_arguments_are_rejected() {
+  let code = [Op(OpCode::GetUpvalue), Arg(0), Op(OpCode::Return)];
+  let function = Function {
+    upvalue_count: 1,
+    ..script(&code, vec![])
+  };
+
+  let result = round_trip("upvalue", &function);
+  assert!(matches!(result, Err(Error::Bytecode(_))));
+
+  let code = [Op(OpCode::Nil), Op(OpCode::Return)];
+  let function = Function {
+    arity: 1,
+    ..script(&code, vec![])
+  };
+
+  let result = round_trip("arity", &function);
+  assert!(matches!(result, Err(Error::Bytecode(_))));
+}
+
+#[test]
+fn methods_on_things_that_are_not_classes_are_runtime_errors() {
+  let code = [
+    Op(OpCode::Nil),
+    Op(OpCode::Nil),
+    Op(OpCode::Method),
+    Arg(0),
+    Arg(0),
+    Op(OpCode::Return),
+  ];
+
+  let function = script(&code, vec![Constant::String("m".into())]);
+  let function = round_trip("method", &function).unwrap();
+
+  let result = Vm::new().interpret(function);
+  assert!(matches!(result, Err(Error::VmRuntime(..))));
+}